@@ -1,44 +1,98 @@
-use std::{env, error::Error, fs::File, io::{BufReader, BufRead}};
+use std::{env, error::Error, fs::File, io::{self, BufReader, BufRead}};
+
+mod window;
+
+use window::{Aggregate, Comparison, WindowStats};
+
+const USAGE: &str =
+    "Usage: day1 <input|-> [window <n>] [agg <sum|min|max|mean>] [top <k>] [every <n>] [emit]";
+
+fn running(stats: &WindowStats) {
+    println!(
+        "[{} readings] increases: {} decreases: {} plateaus: {} longest run: {}",
+        stats.readings, stats.increases, stats.decreases, stats.plateaus, stats.longest_run
+    );
+}
+
+fn report(stats: &WindowStats) {
+    println!("Readings: {} Windows: {}", stats.readings, stats.windows);
+    println!("Increases: {} Decreases: {} Plateaus: {}", stats.increases, stats.decreases, stats.plateaus);
+    println!("Longest increasing run: {}", stats.longest_run);
+    for jump in &stats.jumps {
+        println!("Jump of {} into window {}", jump.delta, jump.index);
+    }
+}
+
+fn emit(c: &Comparison) {
+    println!("window {}: {} ({:?} by {})", c.index, c.value, c.trend, c.delta);
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("Insufficient arguments");
+    if args.len() < 2 {
+        panic!("{}", USAGE);
     }
 
-    // Part 1
-    let file = File::open(&args[1])?;
-    let mut old_depth = 0;
-    let mut inc_count = -1;
-    for line in BufReader::new(file).lines() {
-        if let Ok(nstr) = line {
-            let new_depth: i32 = nstr.parse()?;
-            if new_depth > old_depth {
-                inc_count += 1;
-            }
-            old_depth = new_depth;
+    let (mut size, mut aggregate, mut top_k, mut every, mut emitting) = (None, Aggregate::Sum, 0, None, false);
+    let mut opts = args.iter().skip(2);
+    while let Some(opt) = opts.next() {
+        let mut value = || opts.next().ok_or(USAGE);
+        match opt.as_str() {
+            "window" => size = Some(value()?.parse()?),
+            "agg" => aggregate = value()?.parse()?,
+            "top" => top_k = value()?.parse()?,
+            "every" => every = Some(value()?.parse::<usize>()?).filter(|&n| n > 0),
+            "emit" => emitting = true,
+            _ => panic!("{}", USAGE),
         }
     }
 
-    println!("Increased measurements: {}", inc_count);
-    
-    // Part 2
-    let file = File::open(&args[1])?;
-    let mut old_depths = [0, 0, 0];
-    let mut inc3_count = -3;
-    for line in BufReader::new(file).lines() {
-        if let Ok(nstr) = line {
-            let new_depth: i32 = nstr.parse()?;
-            if new_depth > old_depths[0] {
-                inc3_count += 1;
+    // Without a window, report the two original puzzle answers
+    let custom = args.len() > 2;
+    let mut all_stats =
+        match size {
+            Some(size) => vec![WindowStats::new(size, aggregate, top_k)?],
+            None if custom => vec![WindowStats::new(1, aggregate, top_k)?],
+            None => vec![
+                WindowStats::new(1, Aggregate::Sum, 0)?,
+                WindowStats::new(3, Aggregate::Sum, 0)?,
+            ],
+        };
+
+    let input: Box<dyn BufRead> =
+        if args[1] == "-" {
+            Box::new(io::stdin().lock())
+        } else {
+            Box::new(BufReader::new(File::open(&args[1])?))
+        };
+
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue
+        }
+
+        let depth: i64 = line.trim().parse()?;
+        for stats in &mut all_stats {
+            let comparison = stats.push(depth);
+            if emitting {
+                comparison.iter().for_each(emit);
+            }
+            if every.is_some_and(|n| stats.readings % n == 0) {
+                running(stats);
             }
-            old_depths[0] = old_depths[1];
-            old_depths[1] = old_depths[2];
-            old_depths[2] = new_depth;
         }
     }
 
-    println!("Increased window measurements: {}", inc3_count);
-    
+    if custom {
+        report(&all_stats[0]);
+    } else {
+        // Part 1
+        println!("Increased measurements: {}", all_stats[0].increases);
+
+        // Part 2
+        println!("Increased window measurements: {}", all_stats[1].increases);
+    }
+
     Ok(())
-}
\ No newline at end of file
+}
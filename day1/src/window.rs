@@ -0,0 +1,194 @@
+use std::{collections::VecDeque, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Sum,
+    Min,
+    Max,
+    Mean,
+}
+
+impl FromStr for Aggregate {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sum" => Ok(Aggregate::Sum),
+            "min" => Ok(Aggregate::Min),
+            "max" => Ok(Aggregate::Max),
+            "mean" => Ok(Aggregate::Mean),
+            _ => Err("unknown aggregate"),
+        }
+    }
+}
+
+// Fixed size window over a stream of readings. Sums are kept as a running
+// total and min/max with a monotonic deque, so each push is amortised O(1).
+#[derive(Debug, Clone)]
+pub struct SlidingWindow {
+    size: usize,
+    aggregate: Aggregate,
+    readings: VecDeque<i64>,
+    sum: i64,
+    extremes: VecDeque<i64>,
+}
+
+impl SlidingWindow {
+    pub fn new(size: usize, aggregate: Aggregate) -> Result<SlidingWindow, &'static str> {
+        if size == 0 {
+            return Err("window size must be positive");
+        }
+
+        Ok(SlidingWindow {
+            size,
+            aggregate,
+            readings: VecDeque::with_capacity(size + 1),
+            sum: 0,
+            extremes: VecDeque::new(),
+        })
+    }
+
+    // Returns the aggregate once the window is full
+    pub fn push(&mut self, x: i64) -> Option<f64> {
+        self.readings.push_back(x);
+        self.sum += x;
+
+        // Front of `extremes` is the current min (or max)
+        let aggregate = self.aggregate;
+        let dominated = |e: i64| match aggregate {
+            Aggregate::Min => e > x,
+            _ => e < x,
+        };
+        while self.extremes.back().is_some_and(|&e| dominated(e)) {
+            self.extremes.pop_back();
+        }
+        self.extremes.push_back(x);
+
+        if self.readings.len() > self.size {
+            let old = self.readings.pop_front()?;
+            self.sum -= old;
+            if self.extremes.front() == Some(&old) {
+                self.extremes.pop_front();
+            }
+        }
+
+        if self.readings.len() < self.size {
+            return None;
+        }
+
+        match self.aggregate {
+            Aggregate::Sum => Some(self.sum as f64),
+            Aggregate::Mean => Some(self.sum as f64 / self.size as f64),
+            Aggregate::Min | Aggregate::Max => self.extremes.front().map(|&e| e as f64),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trend {
+    Increase,
+    Decrease,
+    Plateau,
+}
+
+// Window `index` compared to the one before it
+#[derive(Debug, Clone, Copy)]
+pub struct Comparison {
+    pub index: usize,
+    pub value: f64,
+    pub delta: f64,
+    pub trend: Trend,
+}
+
+// Comparisons between consecutive windows, gathered in a single pass and in
+// memory bounded by the window size and `top_k`
+#[derive(Debug, Clone)]
+pub struct WindowStats {
+    window: SlidingWindow,
+    prev: Option<f64>,
+    run: usize,
+    top_k: usize,
+    pub readings: usize,
+    pub windows: usize,
+    pub increases: usize,
+    pub decreases: usize,
+    pub plateaus: usize,
+    pub longest_run: usize,
+    // Largest jumps by magnitude, biggest first
+    pub jumps: Vec<Comparison>,
+}
+
+impl WindowStats {
+    pub fn new(size: usize, aggregate: Aggregate, top_k: usize) -> Result<WindowStats, &'static str> {
+        Ok(WindowStats {
+            window: SlidingWindow::new(size, aggregate)?,
+            prev: None,
+            run: 0,
+            top_k,
+            readings: 0,
+            windows: 0,
+            increases: 0,
+            decreases: 0,
+            plateaus: 0,
+            longest_run: 0,
+            jumps: Vec::with_capacity(top_k + 1),
+        })
+    }
+
+    // Returns the comparison with the previous window, if there is one yet
+    pub fn push(&mut self, x: i64) -> Option<Comparison> {
+        self.readings += 1;
+        let value = self.window.push(x)?;
+
+        let index = self.windows;
+        self.windows += 1;
+
+        let prev = self.prev.replace(value);
+        let comparison = prev.map(|prev| {
+            let trend =
+                if value > prev {
+                    Trend::Increase
+                } else if value < prev {
+                    Trend::Decrease
+                } else {
+                    Trend::Plateau
+                };
+            Comparison {index, value, delta: value - prev, trend}
+        });
+
+        // A run counts windows, so a lone window is a run of 1
+        self.run = match comparison.map(|c| c.trend) {
+            Some(Trend::Increase) => {
+                self.increases += 1;
+                self.run + 1
+            },
+            Some(Trend::Decrease) => {
+                self.decreases += 1;
+                1
+            },
+            Some(Trend::Plateau) => {
+                self.plateaus += 1;
+                1
+            },
+            None => 1,
+        };
+        self.longest_run = self.longest_run.max(self.run);
+
+        if let Some(c) = comparison {
+            self.record_jump(c);
+        }
+        comparison
+    }
+
+    fn record_jump(&mut self, jump: Comparison) {
+        if self.top_k == 0 {
+            return;
+        }
+
+        let pos = self.jumps.partition_point(|j| j.delta.abs() >= jump.delta.abs());
+        if pos < self.top_k {
+            self.jumps.insert(pos, jump);
+            self.jumps.truncate(self.top_k);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::fmt::Write;
+
+use crate::grammar::{BracketGrammar, Check};
+
+fn width(s: &str) -> usize {
+    s.chars().count().max(1)
+}
+
+// Compiler style report for a corrupted or incomplete line, nothing for a
+// balanced one. Columns are counted in characters, starting at 1 in the
+// header. Tabs are not expanded, so carets only line up without them.
+pub fn render(grammar: &BracketGrammar, name: &str, lineno: usize, line: &str, check: &Check)
+    -> Option<String>
+{
+    let gutter = " ".repeat(lineno.to_string().len());
+    let mut out = String::new();
+
+    let (col, marks) = match check {
+        Check::Corrupted {col, found, expected} => {
+            let found = &grammar.pairs[*found];
+            let caret = format!("{:>pad$}{}", "", "^".repeat(width(&found.close)), pad = col);
+            let marks = match expected {
+                Some(open) => {
+                    let opener = &grammar.pairs[open.pair];
+                    writeln!(out, "error[corrupted]: expected `{}`, found `{}`", opener.close, found.close).ok()?;
+                    let dash = format!("{:>pad$}{}", "", "-".repeat(width(&opener.open)), pad = open.col);
+                    let pointer = format!("{:>pad$}|", "", pad = open.col);
+                    let label = format!("{:>pad$}`{}` opened here", "", opener.open, pad = open.col);
+
+                    // The caret always comes after the opener
+                    let first = format!("{}{} expected `{}`", dash, &caret[dash.len()..], opener.close);
+                    vec![first, pointer, label]
+                },
+                None => {
+                    writeln!(out, "error[corrupted]: unexpected `{}` with nothing open", found.close).ok()?;
+                    vec![format!("{} nothing to close", caret)]
+                },
+            };
+            (col, marks)
+        },
+        Check::Incomplete {unclosed, ..} if unclosed.is_empty() => return None,
+        Check::Incomplete {col, unclosed, completion} => {
+            writeln!(out, "error[incomplete]: {} delimiter(s) left open", unclosed.len()).ok()?;
+            let open = unclosed.last()?;
+            let dash = format!("{:>pad$}{}", "", "-".repeat(width(&grammar.pairs[open.pair].open)), pad = open.col);
+            let caret = format!("{:>pad$}^ insert `{}`", "", completion, pad = col - dash.len());
+            let pointer = format!("{:>pad$}|", "", pad = open.col);
+            let label = format!("{:>pad$}last `{}` opened here", "", grammar.pairs[open.pair].open, pad = open.col);
+            (col, vec![format!("{}{}", dash, caret), pointer, label])
+        },
+    };
+
+    writeln!(out, "{}--> {}:{}:{}", gutter, name, lineno, col + 1).ok()?;
+    writeln!(out, "{} |", gutter).ok()?;
+    writeln!(out, "{} | {}", lineno, line).ok()?;
+    for mark in marks {
+        writeln!(out, "{} | {}", gutter, mark).ok()?;
+    }
+    Some(out)
+}
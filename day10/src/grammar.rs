@@ -0,0 +1,172 @@
+use std::{error::Error, str::FromStr};
+
+// Opening and closing delimiters, which may be several characters long
+#[derive(Debug, Clone)]
+pub struct Pair {
+    pub open: String,
+    pub close: String,
+    pub error_score: u64,
+    pub complete_score: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Open(usize),
+    Close(usize),
+}
+
+// Delimiter left open, at a column counted in characters
+#[derive(Debug, Clone, Copy)]
+pub struct Opened {
+    pub pair: usize,
+    pub col: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum Check {
+    // Closer of pair `found` at `col` does not match the last opener, if any
+    Corrupted {col: usize, found: usize, expected: Option<Opened>},
+    // Reached the end at `col` with openers left, `completion` closes them.
+    // Balanced lines have none.
+    Incomplete {col: usize, unclosed: Vec<Opened>, completion: String},
+}
+
+pub enum Score {
+    CompleteScore(u64),
+    ErrorScore(u64),
+}
+
+#[derive(Debug, Clone)]
+pub struct BracketGrammar {
+    pub pairs: Vec<Pair>,
+    pub ignore: Vec<char>,
+    // Completion scores fold as `acc * multiplier + score`
+    pub multiplier: u64,
+}
+
+impl Default for BracketGrammar {
+    fn default() -> Self {
+        let pair = |open: &str, close: &str, error_score, complete_score| Pair {
+            open: open.to_string(),
+            close: close.to_string(),
+            error_score,
+            complete_score,
+        };
+
+        BracketGrammar {
+            pairs: vec![
+                pair("(", ")", 3, 1),
+                pair("[", "]", 57, 2),
+                pair("{", "}", 1197, 3),
+                pair("<", ">", 25137, 4),
+            ],
+            ignore: vec![],
+            multiplier: 5,
+        }
+    }
+}
+
+// `key = value` lines, `#` starts a comment line. Each `pair = <open> <close>
+// <error score> <completion score>` line adds a pair, replacing the default
+// ones, `ignore` lists characters to skip with `\s` for space and `\t` for tab.
+impl FromStr for BracketGrammar {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut grammar = BracketGrammar {pairs: vec![], ..BracketGrammar::default()};
+        for line in s.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue
+            }
+
+            let (key, value) =
+                line.split_once('=')
+                    .map(|(k, v)| (k.trim(), v.trim()))
+                    .ok_or(format!("GrammarError: {}", line))?;
+            match key {
+                "pair" => {
+                    let fields: Vec<&str> = value.split_whitespace().collect();
+                    if fields.len() != 4 {
+                        Err(format!("GrammarError: {}", line))?
+                    }
+                    grammar.pairs.push(Pair {
+                        open: fields[0].to_string(),
+                        close: fields[1].to_string(),
+                        error_score: fields[2].parse()?,
+                        complete_score: fields[3].parse()?,
+                    });
+                },
+                "ignore" => {
+                    grammar.ignore = value.replace("\\s", " ").replace("\\t", "\t").chars().collect()
+                },
+                "multiplier" => grammar.multiplier = value.parse()?,
+                _ => Err(format!("GrammarError: unknown key {}", key))?
+            }
+        }
+
+        if grammar.pairs.is_empty() {
+            grammar.pairs = BracketGrammar::default().pairs;
+        }
+        Ok(grammar)
+    }
+}
+
+impl BracketGrammar {
+    // Longest delimiter starting `s`. On a tie the closer expected next wins,
+    // then openers, so a pair like `" "` closes only when it is expected.
+    pub fn token_at(&self, s: &str, expected: Option<usize>) -> Option<(Token, usize)> {
+        let rank = |t: Token| match t {
+            Token::Close(p) if Some(p) == expected => 2,
+            Token::Open(_) => 1,
+            Token::Close(_) => 0,
+        };
+
+        self.pairs
+            .iter()
+            .enumerate()
+            .flat_map(|(p, pair)| [(Token::Open(p), &pair.open), (Token::Close(p), &pair.close)])
+            .filter(|(_, delim)| s.starts_with(delim.as_str()))
+            .map(|(t, delim)| (t, delim.len()))
+            .max_by_key(|&(t, len)| (len, rank(t)))
+    }
+
+    // Scans the line, stopping at the first closer that does not match
+    pub fn check(&self, s: &str) -> Result<Check, &'static str> {
+        let mut stack: Vec<Opened> = vec![];
+
+        let (mut rest, mut col) = (s, 0);
+        while let Some(c) = rest.chars().next() {
+            let (token, len) = match self.token_at(rest, stack.last().map(|o| o.pair)) {
+                Some((token, len)) => (Some(token), len),
+                None if self.ignore.contains(&c) => (None, c.len_utf8()),
+                None => return Err("invalid character"),
+            };
+
+            match token {
+                Some(Token::Open(pair)) => stack.push(Opened {pair, col}),
+                Some(Token::Close(pair)) => match stack.pop() {
+                    Some(open) if open.pair == pair => {},
+                    expected => return Ok(Check::Corrupted {col, found: pair, expected}),
+                },
+                None => {},
+            }
+            col += rest[..len].chars().count();
+            rest = &rest[len..];
+        }
+
+        let completion = stack.iter().rev().map(|o| self.pairs[o.pair].close.as_str()).collect();
+        Ok(Check::Incomplete {col, unclosed: stack, completion})
+    }
+
+    pub fn score(&self, check: &Check) -> Score {
+        match check {
+            Check::Corrupted {found, ..} => Score::ErrorScore(self.pairs[*found].error_score),
+            Check::Incomplete {unclosed, ..} => Score::CompleteScore(
+                unclosed.iter()
+                        .rev()
+                        .fold(0, |acc, o| acc * self.multiplier + self.pairs[o.pair].complete_score)
+            ),
+        }
+    }
+}
@@ -1,79 +1,61 @@
-use std::{error::Error, env, fs::File, io::{BufReader, BufRead}};
+use std::{error::Error, env, fs::{self, File}, io::{BufReader, BufRead}};
 
 use itertools::Itertools;
 
-const OPENS: [char; 4] = ['(', '[', '{', '<'];
-const CLOSES: [char; 4] = [')', ']', '}', '>'];
-const MATCHES: [(char, char); 4] = [
-    ('(', ')'),
-    ('[', ']'),
-    ('{', '}'),
-    ('<', '>'),
-];
-const SCORING: [(char, u64); 4] = [
-    (')', 3),
-    (']', 57),
-    ('}', 1197),
-    ('>', 25137),
-];
-const COMPLETE_SCORE: [(char, u64); 4] = [
-    ('(', 1),
-    ('[', 2),
-    ('{', 3),
-    ('<', 4),
-];
+mod diagnostic;
+mod grammar;
+mod repair;
+use grammar::{BracketGrammar, Check, Score};
 
-enum Score {
-    CompleteScore(u64),
-    ErrorScore(u64),
-}
-
-fn lookup<T: Copy>(xs: &[(char, T)], c: char) -> Result<T, &'static str> {
-    xs.iter().find(|&&(k, _)| k == c).map(|&(_, t)| t).ok_or("bad lookup")
-}
+const USAGE: &str = "Usage: day10 <input> [grammar <file>] [report] [repair]";
 
-fn parse_score(s: &str) -> Result<Score, &'static str> {
-    let mut stack: Vec<char> = vec![];
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = env::args().collect_vec();
+    if args.len() < 2 {
+        panic!("{}", USAGE);
+    }
 
-    for c in s.chars() {
-        if OPENS.contains(&c) {
-            stack.push(c);
-        } else if CLOSES.contains(&c) {
-            let score = lookup(&SCORING, c).map(Score::ErrorScore);
-            match stack.pop() {
-                Some(open) => {
-                    match lookup(&MATCHES, open) {
-                        Ok(close) => if close != c {
-                            return score
-                        },
-                        Err(s) => return Err(s)
-                    }
-                }
-                None => return score
-            }
-        } else {
-            return Err("invalid character");
+    let (mut grammar, mut report, mut repair) = (BracketGrammar::default(), false, false);
+    let mut opts = args.iter().skip(2);
+    while let Some(opt) = opts.next() {
+        match opt.as_str() {
+            "grammar" => grammar = fs::read_to_string(opts.next().ok_or(USAGE)?)?.parse()?,
+            "report" => report = true,
+            "repair" => repair = true,
+            _ => panic!("{}", USAGE),
         }
     }
 
-    let sc: u64 = 
-        stack.iter()
-             .try_rfold(0, |acc, &x| lookup(&COMPLETE_SCORE, x).map(|v| acc * 5 + v))?;
-    
-    Ok(Score::CompleteScore(sc))
-}
-
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = env::args().collect_vec();
-    if args.len() != 2 {
-        panic!("Failed to read input");
-    }
-    
     let file = File::open(&args[1])?;
     let lines: Vec<String> = BufReader::new(file).lines().try_collect()?;
 
     // Part 1
-    let scores: Vec<Score> = lines.iter().map(|s| parse_score(s)).try_collect()?;
+    let checks: Vec<Check> = lines.iter().map(|s| grammar.check(s)).try_collect()?;
+    if report {
+        for (i, (line, check)) in lines.iter().zip(&checks).enumerate() {
+            if let Some(diag) = diagnostic::render(&grammar, &args[1], i + 1, line, check) {
+                println!("{}", diag);
+            }
+        }
+    }
+
+    if repair {
+        for (i, (line, check)) in lines.iter().zip(&checks).enumerate() {
+            match check {
+                Check::Incomplete {unclosed, ..} if unclosed.is_empty() => {},
+                Check::Incomplete {completion, ..} => {
+                    println!("line {}: complete with `{}`: {}{}", i + 1, completion, line, completion);
+                },
+                Check::Corrupted {..} => {
+                    let fixed = grammar.repair(line)?;
+                    println!("line {}: {} edit(s): {}", i + 1, fixed.edits.len(), fixed.line);
+                    fixed.edits.iter().for_each(|e| println!("    {}", e));
+                },
+            }
+        }
+    }
+
+    let scores = checks.iter().map(|c| grammar.score(c)).collect_vec();
     let err_score: u64 =
         scores
             .iter()
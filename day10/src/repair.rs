@@ -0,0 +1,210 @@
+use std::fmt;
+
+use crate::grammar::{BracketGrammar, Token};
+
+#[derive(Debug, Clone)]
+pub enum Edit {
+    Insert {col: usize, text: String},
+    Delete {col: usize, text: String},
+    Replace {col: usize, from: String, to: String},
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Edit::Insert {col, text} => write!(f, "insert `{}` at column {}", text, col + 1),
+            Edit::Delete {col, text} => write!(f, "delete `{}` at column {}", text, col + 1),
+            Edit::Replace {col, from, to} => write!(f, "replace `{}` at column {} with `{}`", from, col + 1, to),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Repair {
+    pub edits: Vec<Edit>,
+    pub line: String,
+}
+
+// Delimiter of the line, with the ignored text before it and its column
+struct Lexeme<'a> {
+    token: Token,
+    before: &'a str,
+    col: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Choice {
+    // Token i opens pair p, closed by token k
+    Match {k: usize, pair: usize},
+    // Token i opens pair p, closed by a closer inserted before token k
+    InsertClose {k: usize, pair: usize},
+    // An inserted opener of pair p, closed by token k
+    InsertOpen {k: usize, pair: usize},
+    Delete,
+}
+
+enum Event {
+    Token(usize, Option<Token>),
+    Insert(Token),
+}
+
+fn pair_of(t: Token) -> usize {
+    match t {
+        Token::Open(p) | Token::Close(p) => p,
+    }
+}
+
+struct Solver<'a> {
+    tokens: Vec<Token>,
+    // cost[i][j] is the fewest edits balancing tokens i..j
+    cost: Vec<Vec<u32>>,
+    lexemes: Vec<Lexeme<'a>>,
+}
+
+impl<'a> Solver<'a> {
+    // Every way to balance tokens i..j, in order of preference on equal cost
+    fn options(&self, i: usize, j: usize) -> Vec<(u32, Choice)> {
+        let (t, c) = (self.tokens[i], &self.cost);
+        let mut options = vec![];
+
+        for k in i + 1..j {
+            let pair = match t {
+                Token::Open(p) => p,
+                Token::Close(_) => pair_of(self.tokens[k]),
+            };
+            let edits = (t != Token::Open(pair)) as u32 + (self.tokens[k] != Token::Close(pair)) as u32;
+            options.push((edits + c[i + 1][k] + c[k + 1][j], Choice::Match {k, pair}));
+        }
+        if let Token::Open(pair) = t {
+            options.extend((i + 1..=j).map(|k| (1 + c[i + 1][k] + c[k][j], Choice::InsertClose {k, pair})));
+        }
+        for k in i..j {
+            if let Token::Close(pair) = self.tokens[k] {
+                options.push((1 + c[i][k] + c[k + 1][j], Choice::InsertOpen {k, pair}));
+            }
+        }
+        options.push((1 + c[i + 1][j], Choice::Delete));
+        options
+    }
+
+    fn best(&self, i: usize, j: usize) -> (u32, Choice) {
+        self.options(i, j)
+            .into_iter()
+            .reduce(|best, o| if o.0 < best.0 { o } else { best })
+            .expect("there is always a deletion")
+    }
+
+    fn solve(&mut self) {
+        let n = self.tokens.len();
+        self.cost = vec![vec![0; n + 1]; n + 1];
+        for len in 1..=n {
+            for i in 0..=n - len {
+                self.cost[i][i + len] = self.best(i, i + len).0;
+            }
+        }
+    }
+
+    // Walks the best choices, in line order
+    fn events(&self, i: usize, j: usize, out: &mut Vec<Event>) {
+        if i >= j {
+            return;
+        }
+
+        match self.best(i, j).1 {
+            Choice::Match {k, pair} => {
+                out.push(Event::Token(i, Some(Token::Open(pair))));
+                self.events(i + 1, k, out);
+                out.push(Event::Token(k, Some(Token::Close(pair))));
+                self.events(k + 1, j, out);
+            },
+            Choice::InsertClose {k, pair} => {
+                out.push(Event::Token(i, Some(Token::Open(pair))));
+                self.events(i + 1, k, out);
+                out.push(Event::Insert(Token::Close(pair)));
+                self.events(k, j, out);
+            },
+            Choice::InsertOpen {k, pair} => {
+                out.push(Event::Insert(Token::Open(pair)));
+                self.events(i, k, out);
+                out.push(Event::Token(k, Some(Token::Close(pair))));
+                self.events(k + 1, j, out);
+            },
+            Choice::Delete => {
+                out.push(Event::Token(i, None));
+                self.events(i + 1, j, out);
+            },
+        }
+    }
+}
+
+impl BracketGrammar {
+    fn text(&self, t: Token) -> &str {
+        match t {
+            Token::Open(p) => &self.pairs[p].open,
+            Token::Close(p) => &self.pairs[p].close,
+        }
+    }
+
+    // Fewest insertions, deletions and replacements of delimiters that
+    // balance the line, ignored characters are kept as they are. Interval
+    // dynamic programming over the delimiters, O(n^3) in their number.
+    pub fn repair(&self, s: &str) -> Result<Repair, &'static str> {
+        let mut lexemes = vec![];
+        let mut stack: Vec<usize> = vec![];
+        let (mut rest, mut col, mut start) = (s, 0, 0);
+        while let Some(c) = rest.chars().next() {
+            match self.token_at(rest, stack.last().copied()) {
+                Some((token, len)) => {
+                    match token {
+                        Token::Open(p) => stack.push(p),
+                        Token::Close(_) => { stack.pop(); },
+                    }
+                    let before = &s[start..s.len() - rest.len()];
+                    lexemes.push(Lexeme {token, before, col});
+                    col += rest[..len].chars().count();
+                    rest = &rest[len..];
+                    start = s.len() - rest.len();
+                },
+                None if self.ignore.contains(&c) => {
+                    col += 1;
+                    rest = &rest[c.len_utf8()..];
+                },
+                None => return Err("invalid character"),
+            }
+        }
+        let trailing = &s[start..];
+
+        let mut solver = Solver {tokens: lexemes.iter().map(|l| l.token).collect(), cost: vec![], lexemes};
+        solver.solve();
+        let mut events = vec![];
+        solver.events(0, solver.tokens.len(), &mut events);
+
+        // Insertions go right after the last original delimiter, at `end`
+        let (mut line, mut edits, mut end) = (String::new(), vec![], 0);
+        for event in events {
+            match event {
+                Event::Token(m, kept) => {
+                    let lexeme = &solver.lexemes[m];
+                    let text = self.text(lexeme.token).to_string();
+                    end = lexeme.col + text.chars().count();
+                    line.push_str(lexeme.before);
+                    match kept {
+                        None => edits.push(Edit::Delete {col: lexeme.col, text}),
+                        Some(t) if t == lexeme.token => line.push_str(&text),
+                        Some(t) => {
+                            line.push_str(self.text(t));
+                            edits.push(Edit::Replace {col: lexeme.col, from: text, to: self.text(t).to_string()});
+                        },
+                    }
+                },
+                Event::Insert(t) => {
+                    line.push_str(self.text(t));
+                    edits.push(Edit::Insert {col: end, text: self.text(t).to_string()});
+                },
+            }
+        }
+        line.push_str(trailing);
+
+        Ok(Repair {edits, line})
+    }
+}
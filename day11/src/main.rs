@@ -1,91 +1,75 @@
-use std::{env, fs::File, error::Error, io::{BufReader, BufRead}, collections::VecDeque};
+use std::{env, fs::File, error::Error, io::{BufReader, BufRead}};
 
 use itertools::{Itertools, process_results};
 use utils::Grid;
 
-fn step(grid: &mut Grid<u8>) -> i32 {
-    let (w, h) = grid.size();
-
-    // Add 1 to each
-    grid.iter_mut_coords()
-        .flatten()
-        .for_each(|(v, _)| *v += 1);
-    
-    // Flash each octopus
-    let mut num_flashed = 0;
-    let mut flashed = vec![vec![false; w]; h];
-    let mut working: VecDeque<_> =
-        grid.iter_coords()
-            .flatten()
-            .filter_map(|t| {
-                if *t.0 > 9 {
-                    Some(t.1)
-                } else {
-                    None
-                }
-            })
-            .collect();
-            
-    while let Some((x, y)) = working.pop_front() {
-        if flashed[y][x] {
-            continue
-        }
+mod sim;
+use sim::{Neighbourhood, OctopusSim, Rules};
 
-        flashed[y][x] = true;
-        num_flashed += 1;
-        *grid.get_mut(x, y).unwrap() = 0;
-
-        grid.iter_neighbors(x, y)
-            .for_each(|(nx, ny)| {
-                let n = grid.get_mut(nx, ny).unwrap();
-                if !flashed[ny][nx] {
-                    *n += 1;
-                    if *n > 9 {
-                        working.push_back((nx, ny));
-                    }
-                }
-            });
-    }
-
-    num_flashed
-}
+const USAGE: &str =
+    "Usage: day11 <input> [threshold <n>] [reset <n>] [increment <n>] [neighbours <4|8|hex>] [limit <n>] [frames <output>] [compare]";
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = env::args().collect_vec();
-    if args.len() != 2 {
-        panic!("Could not read input");
+    if args.len() < 2 {
+        panic!("{}", USAGE);
+    }
+
+    let (mut rules, mut limit, mut frames, mut compare) = (Rules::default(), 100_000, None, false);
+    let mut opts = args.iter().skip(2);
+    while let Some(opt) = opts.next() {
+        let mut value = || opts.next().ok_or(USAGE);
+        match opt.as_str() {
+            "threshold" => rules.threshold = value()?.parse()?,
+            "reset" => rules.reset = value()?.parse()?,
+            "increment" => rules.increment = value()?.parse()?,
+            "neighbours" => rules.neighbourhood = value()?.parse()?,
+            "limit" => limit = value()?.parse()?,
+            "frames" => frames = Some(value()?),
+            "compare" => compare = true,
+            _ => panic!("{}", USAGE),
+        }
     }
 
     let file = File::open(&args[1])?;
-    let mut grid: Grid<u8> =
+    let grid: Grid<u32> =
         process_results(
             BufReader::new(file).lines(),
             |iter| Grid::parse_grid(iter, "")
         )??;
 
+    let mut sim = OctopusSim::new(grid.clone(), rules);
+    if frames.is_some() {
+        sim = sim.recording();
+    }
+
     // Part 1
-    let flashed_totals =
-        (0..100)
-            .map(|_| step(&mut grid))
-            .collect_vec();
-    
-    println!("Flashed: {}", flashed_totals.iter().sum::<i32>());
+    let flashed_totals = (0..100).map(|_| sim.step()).collect_vec();
+    println!("Flashed: {}", flashed_totals.iter().sum::<usize>());
 
     // Part 2
-    let ttl = (grid.size().0 * grid.size().1) as i32;
-    if let Some(p) = flashed_totals.iter().position(|&v| v == ttl) {
-        println!("Simultaneous: {}", p + 1);
-    } else {
-        let mut simul = 101;
-        loop {
-            if step(&mut grid) == ttl {
-                println!("Simultaneous: {}", simul);
-                break;
-            } else {
-                simul += 1;
-            }
+    let (w, h) = grid.size();
+    let sync =
+        match flashed_totals.iter().position(|&v| v == w * h) {
+            Some(p) => Some(p + 1),
+            None => sim.sync_step(limit),
+        };
+    match sync {
+        Some(s) => println!("Simultaneous: {}", s),
+        None => println!("Simultaneous: none within {} steps", limit),
+    }
+
+    if let Some(path) = frames {
+        sim.write_frames(File::create(path)?)?;
+    }
+
+    if compare {
+        for neighbourhood in [Neighbourhood::Four, Neighbourhood::Eight, Neighbourhood::Hex] {
+            let mut sim = OctopusSim::new(grid.clone(), Rules {neighbourhood, ..rules});
+            let sync = sim.sync_step(limit).map_or(format!("none within {}", limit), |s| s.to_string());
+            println!("{:?} ({} octopuses): simultaneous {}", neighbourhood, w * h, sync);
         }
     }
-    
+
     Ok(())
 }
@@ -0,0 +1,147 @@
+use std::{collections::VecDeque, error::Error, io::Write, str::FromStr};
+
+use utils::Grid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Neighbourhood {
+    Four,
+    Eight,
+    // Hexagonal cells, odd rows shifted half a cell to the right
+    Hex,
+}
+
+impl FromStr for Neighbourhood {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "4" => Ok(Neighbourhood::Four),
+            "8" => Ok(Neighbourhood::Eight),
+            "hex" => Ok(Neighbourhood::Hex),
+            _ => Err(format!("NeighbourhoodError: {}", s).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Rules {
+    // An octopus flashes once its energy is above this
+    pub threshold: u32,
+    pub reset: u32,
+    // Energy gained every step, and from every flashing neighbour
+    pub increment: u32,
+    pub neighbourhood: Neighbourhood,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {threshold: 9, reset: 0, increment: 1, neighbourhood: Neighbourhood::Eight}
+    }
+}
+
+#[derive(Debug)]
+pub struct OctopusSim {
+    pub grid: Grid<u32>,
+    pub rules: Rules,
+    pub steps: usize,
+    // Octopuses that flashed, one map per step, kept only when recording
+    pub flash_maps: Option<Vec<Grid<bool>>>,
+}
+
+impl OctopusSim {
+    pub fn new(grid: Grid<u32>, rules: Rules) -> OctopusSim {
+        OctopusSim {grid, rules, steps: 0, flash_maps: None}
+    }
+
+    pub fn recording(mut self) -> OctopusSim {
+        self.flash_maps = Some(vec![]);
+        self
+    }
+
+    fn neighbours(&self, x: usize, y: usize) -> Vec<(usize, usize)> {
+        let (w, h) = self.grid.size();
+        let offsets: &[(isize, isize)] = match self.rules.neighbourhood {
+            Neighbourhood::Eight => return self.grid.iter_neighbors(x, y).collect(),
+            Neighbourhood::Four => &[(0, -1), (-1, 0), (1, 0), (0, 1)],
+            Neighbourhood::Hex if y.is_multiple_of(2) => &[(-1, -1), (0, -1), (-1, 0), (1, 0), (-1, 1), (0, 1)],
+            Neighbourhood::Hex => &[(0, -1), (1, -1), (-1, 0), (1, 0), (0, 1), (1, 1)],
+        };
+
+        offsets.iter()
+               .map(|&(dx, dy)| (x.wrapping_add_signed(dx), y.wrapping_add_signed(dy)))
+               .filter(|&(nx, ny)| nx < w && ny < h)
+               .collect()
+    }
+
+    // Returns how many flashed, every octopus flashes at most once a step
+    pub fn step(&mut self) -> usize {
+        let (w, h) = self.grid.size();
+        let Rules {threshold, reset, increment, ..} = self.rules;
+
+        self.grid
+            .iter_mut_coords()
+            .flatten()
+            .for_each(|(v, _)| *v += increment);
+
+        let mut flashed = Grid::filled(w, h, false);
+        let mut num_flashed = 0;
+        let mut working: VecDeque<_> =
+            self.grid
+                .iter_coords()
+                .flatten()
+                .filter(|&(&v, _)| v > threshold)
+                .map(|(_, c)| c)
+                .collect();
+
+        while let Some((x, y)) = working.pop_front() {
+            if *flashed.index(x, y) {
+                continue
+            }
+
+            *flashed.index_mut(x, y) = true;
+            num_flashed += 1;
+            *self.grid.index_mut(x, y) = reset;
+
+            for (nx, ny) in self.neighbours(x, y) {
+                if !flashed.index(nx, ny) {
+                    let n = self.grid.index_mut(nx, ny);
+                    *n += increment;
+                    if *n > threshold {
+                        working.push_back((nx, ny));
+                    }
+                }
+            }
+        }
+
+        self.steps += 1;
+        if let Some(maps) = self.flash_maps.as_mut() {
+            maps.push(flashed);
+        }
+        num_flashed
+    }
+
+    // First step on which every octopus flashes, giving up after `limit` steps
+    pub fn sync_step(&mut self, limit: usize) -> Option<usize> {
+        let (w, h) = self.grid.size();
+        while self.steps < limit {
+            if self.step() == w * h {
+                return Some(self.steps);
+            }
+        }
+        None
+    }
+
+    // One frame per recorded step, `#` for a flash, frames split by blank lines
+    pub fn write_frames<W: Write>(&self, mut w: W) -> Result<(), Box<dyn Error>> {
+        let maps = self.flash_maps.as_ref().ok_or("flash maps were not recorded")?;
+        for (i, map) in maps.iter().enumerate() {
+            writeln!(w, "step {}", i + 1)?;
+            for row in map.iter_coords() {
+                let line: String = row.map(|(&f, _)| if f { '#' } else { '.' }).collect();
+                writeln!(w, "{}", line)?;
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+}
@@ -1,52 +1,220 @@
-use std::{env, error::Error, fs::File, io::{BufRead, BufReader}};
+use std::{env, error::Error, fs::File, io::{BufRead, BufReader, BufWriter, Write}, str::FromStr};
+
+#[derive(Debug, Clone)]
+struct Instruction {
+    op: String,
+    amt: i64,
+}
+
+impl FromStr for Instruction {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (op, amt) =
+            s.trim()
+             .split_once(' ')
+             .ok_or(format!("InstructionError: {}", s))?;
+
+        Ok(Instruction {op: op.to_string(), amt: amt.trim().parse()?})
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct State {
+    pos: i64,
+    depth: i64,
+    aim: i64,
+}
+
+// Semantics of each instruction, unknown ops are errors
+trait InstructionSet {
+    fn execute(&self, state: &mut State, ins: &Instruction) -> Result<(), String>;
+}
+
+// forward moves, up/down change depth directly
+struct Plain;
+
+impl InstructionSet for Plain {
+    fn execute(&self, state: &mut State, ins: &Instruction) -> Result<(), String> {
+        match ins.op.as_str() {
+            "forward" => state.pos += ins.amt,
+            "up" => state.depth -= ins.amt,
+            "down" => state.depth += ins.amt,
+            op => return Err(format!("Unknown command: {}", op))
+        }
+        Ok(())
+    }
+}
+
+// up/down change aim, forward moves along it
+struct Aimed;
+
+impl InstructionSet for Aimed {
+    fn execute(&self, state: &mut State, ins: &Instruction) -> Result<(), String> {
+        match ins.op.as_str() {
+            "forward" => {
+                state.pos += ins.amt;
+                state.depth += state.aim * ins.amt;
+            },
+            "up" => state.aim -= ins.amt,
+            "down" => state.aim += ins.amt,
+            op => return Err(format!("Unknown command: {}", op))
+        }
+        Ok(())
+    }
+}
+
+struct Submarine<'a> {
+    model: &'a dyn InstructionSet,
+    state: State,
+    trace: Option<Vec<State>>,
+}
+
+impl<'a> Submarine<'a> {
+    fn new(model: &'a dyn InstructionSet) -> Submarine<'a> {
+        Submarine {model, state: State::default(), trace: None}
+    }
+
+    // Also record the state after every instruction
+    fn traced(model: &'a dyn InstructionSet) -> Submarine<'a> {
+        Submarine {trace: Some(vec![]), ..Submarine::new(model)}
+    }
+
+    fn execute(&mut self, ins: &Instruction) -> Result<(), String> {
+        self.model.execute(&mut self.state, ins)?;
+        if let Some(trace) = &mut self.trace {
+            trace.push(self.state);
+        }
+        Ok(())
+    }
+
+    fn run(&mut self, program: &[Instruction]) -> Result<State, String> {
+        for (i, ins) in program.iter().enumerate() {
+            self.execute(ins).map_err(|e| format!("line {}: {}", i + 1, e))?;
+        }
+        Ok(self.state)
+    }
+}
+
+// Number of times the sum over a sliding window increases. Consecutive windows
+// share all but one reading, so only the readings entering and leaving matter.
+fn count_increases(depths: &[i64], window: usize) -> usize {
+    depths.iter()
+          .zip(depths.iter().skip(window))
+          .filter(|&(old, new)| new > old)
+          .count()
+}
+
+#[derive(Debug)]
+struct CourseStats {
+    steps: usize,
+    max_depth: i64,
+    max_depth_step: usize,
+    max_depth_pos: i64,
+    increases: usize,
+    window_increases: usize,
+}
+
+impl CourseStats {
+    // Depth profile is the depth after each step, the start is not counted
+    fn from_trace(trace: &[State]) -> Option<CourseStats> {
+        let (step, deepest) =
+            trace.iter()
+                 .enumerate()
+                 .rev()
+                 .max_by_key(|(_, s)| s.depth)?;
+        let depths: Vec<i64> = trace.iter().map(|s| s.depth).collect();
+
+        Some(CourseStats {
+            steps: trace.len(),
+            max_depth: deepest.depth,
+            max_depth_step: step + 1,
+            max_depth_pos: deepest.pos,
+            increases: count_increases(&depths, 1),
+            window_increases: count_increases(&depths, 3),
+        })
+    }
+
+    fn show(&self) {
+        println!("  steps: {}", self.steps);
+        println!("  max depth: {} at step {} (pos {})", self.max_depth, self.max_depth_step, self.max_depth_pos);
+        println!("  depth increases: {}", self.increases);
+        println!("  window depth increases: {}", self.window_increases);
+    }
+}
+
+fn write_csv<W: Write>(mut w: W, courses: &[(&str, &[Instruction], &[State])]) -> Result<(), Box<dyn Error>> {
+    writeln!(w, "model,step,op,amt,pos,depth,aim")?;
+    for &(name, program, states) in courses {
+        for (step, (ins, s)) in program.iter().zip(states).enumerate() {
+            writeln!(w, "{},{},{},{},{},{},{}", name, step + 1, ins.op, ins.amt, s.pos, s.depth, s.aim)?;
+        }
+    }
+    Ok(())
+}
+
+const USAGE: &str = "Usage: day2 <input> [trace] [stats] [csv <output>]";
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("Failed to read input");
+    if args.len() < 2 {
+        panic!("{}", USAGE);
     }
 
-    // Part 1
-    let file = File::open(&args[1])?;
-    let mut pos = 0;
-    let mut depth = 0;
-    for line in BufReader::new(file).lines() {
-        if let Ok(cmdstr) = line {
-            let cmd: Vec<&str> = cmdstr.split(' ').collect();
-            let amt: i32 = cmd[1].parse()?;
-            match cmd[0] {
-                "forward" => pos += amt,
-                "up" => depth -= amt,
-                "down" => depth += amt,
-                _ => panic!("Unknown command")
-            }
+    let (mut trace, mut stats, mut csv) = (false, false, None);
+    let mut opts = args.iter().skip(2);
+    while let Some(opt) = opts.next() {
+        match opt.as_str() {
+            "trace" => trace = true,
+            "stats" => stats = true,
+            "csv" => csv = Some(opts.next().expect(USAGE)),
+            _ => panic!("{}", USAGE),
         }
     }
+    let replay = trace || stats || csv.is_some();
 
-    println!("pos x depth: {}", pos * depth);
-
-    // Part 2
     let file = File::open(&args[1])?;
-    let mut pos = 0;
-    let mut depth = 0;
-    let mut aim = 0;
-    for line in BufReader::new(file).lines() {
-        if let Ok(cmdstr) = line {
-            let cmd: Vec<&str> = cmdstr.split(' ').collect();
-            let amt: i32 = cmd[1].parse()?;
-            match cmd[0] {
-                "forward" => {
-                    pos += amt;
-                    depth += aim * amt;
-                },
-                "up" => aim -= amt,
-                "down" => aim += amt,
-                _ => panic!("Unknown command")
+    let program: Vec<Instruction> =
+        BufReader::new(file)
+            .lines()
+            .map(|r| r.map_err(|e| e.into()).and_then(|s| s.parse()))
+            .collect::<Result<_, Box<dyn Error>>>()?;
+
+    let models: [(&str, &dyn InstructionSet); 2] = [
+        ("plain", &Plain),
+        ("aimed", &Aimed),
+    ];
+
+    let mut traces = vec![];
+    for (name, model) in models {
+        let mut sub =
+            if replay {
+                Submarine::traced(model)
+            } else {
+                Submarine::new(model)
+            };
+        let state = sub.run(&program)?;
+        let states = sub.trace.unwrap_or_default();
+
+        if trace {
+            for (ins, s) in program.iter().zip(&states) {
+                println!("{} {}: pos {} depth {} aim {}", ins.op, ins.amt, s.pos, s.depth, s.aim);
+            }
+        }
+        println!("{} pos x depth: {}", name, state.pos * state.depth);
+        if stats {
+            if let Some(course) = CourseStats::from_trace(&states) {
+                course.show();
             }
         }
+
+        traces.push((name, states));
     }
 
-    println!("real pos x depth: {}", pos * depth);
+    if let Some(path) = csv {
+        let courses = traces.iter().map(|(n, s)| (*n, &program[..], &s[..])).collect::<Vec<_>>();
+        write_csv(BufWriter::new(File::create(path)?), &courses)?;
+    }
 
     Ok(())
-}
\ No newline at end of file
+}
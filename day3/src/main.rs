@@ -1,104 +1,248 @@
-use std::{collections::HashSet, env, error::Error, fs::File, io::{BufRead, BufReader}};
-
-// Part 1
-fn part1(input: &String) -> Result<(), Box<dyn Error>> {
-    let file = File::open(input)?;
-    let mut lines = 0;
-    let mut counts: Vec<u32> = Vec::new();
-    for line in BufReader::new(file).lines() {
-        if let Ok(bin) = line {
-            if counts.len() != bin.len() {
-                counts.resize(bin.len(), 0);
-            }
+use std::{env, error::Error, fs::File, io::{BufRead, BufReader}, str::FromStr};
 
-            for (i, c) in bin.chars().enumerate() {
-                let d = c.to_digit(10).unwrap();
-                counts[i] += d;
-            }
+use itertools::{Itertools, process_results};
+
+// Which bit wins when a column has as many ones as zeros
+#[derive(Debug, Clone, Copy)]
+enum TieBreak {
+    One,
+    Zero,
+    Fail,
+}
 
-            lines += 1;
+impl FromStr for TieBreak {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "one" => Ok(TieBreak::One),
+            "zero" => Ok(TieBreak::Zero),
+            "fail" => Ok(TieBreak::Fail),
+            _ => Err("unknown tie break"),
         }
     }
+}
 
-    let gamma = counts.iter()
-                          .map(|c| *c > lines/2)
-                          .fold(0, |acc, c| (acc << 1) + (c as i32));
-    let epsilon = (1 << counts.len()) - 1 - gamma;
-    
-    println!("gamma: {} epsilon: {} product: {}", gamma, epsilon, gamma * epsilon);
-
-    Ok(())
+#[derive(Debug, Clone, Copy)]
+enum BitCriteria {
+    MostCommon(TieBreak),
+    LeastCommon(TieBreak),
+    Fixed(bool),
 }
 
-// Part 2
-fn part2(input: &String) -> Result<(), Box<dyn Error>> {
-    let file = File::open(input)?;
-    let mut gamma_candidates: HashSet<String> =
-        BufReader::new(file).lines()
-                                  .map(|x| x.unwrap())
-                                  .collect();
-
-    let file = File::open(input)?;
-    let mut epsilon_candidates: HashSet<String> =
-        BufReader::new(file).lines()
-                                  .map(|x| x.unwrap())
-                                  .collect();
-
-    let n = gamma_candidates.iter().nth(0).unwrap().len();
-    assert!(n > 0);
-
-    fn candidate_bit(v: &HashSet<String>, index: usize, is_gamma: bool) -> char {
-        let count: usize =
-            v.iter()
-             .map(|s| s.chars().nth(index).unwrap().to_digit(10).unwrap() as usize)
-             .sum();
-        let total = v.len();
-        if count == 0 {
-            '0'
-        } else if count == total {
-            '1'
-        } else if is_gamma {
-            if count >= total/2 {
-                '1'
-            } else {
-                '0'
-            }
-        } else {
-            if count >= total/2 {
-                '0'
-            } else {
-                '1'
-            }
+impl BitCriteria {
+    // One criterion per character: M/L keep the most/least common bit with
+    // the life support tie breaks, 0/1 keep a fixed bit
+    fn parse_spec(s: &str) -> Result<Vec<BitCriteria>, &'static str> {
+        s.chars()
+         .map(|c| match c {
+             'M' => Ok(BitCriteria::MostCommon(TieBreak::One)),
+             'L' => Ok(BitCriteria::LeastCommon(TieBreak::Zero)),
+             '0' => Ok(BitCriteria::Fixed(false)),
+             '1' => Ok(BitCriteria::Fixed(true)),
+             _ => Err("unknown bit criteria"),
+         })
+         .collect()
+    }
+
+    fn select(&self, ones: usize, total: usize) -> Result<bool, &'static str> {
+        let zeros = total - ones;
+        let (most, tie) = match *self {
+            BitCriteria::MostCommon(tie) => (ones > zeros, tie),
+            BitCriteria::LeastCommon(tie) => (ones < zeros, tie),
+            BitCriteria::Fixed(bit) => return Ok(bit),
+        };
+
+        if ones != zeros {
+            return Ok(most);
         }
+        match tie {
+            TieBreak::One => Ok(true),
+            TieBreak::Zero => Ok(false),
+            TieBreak::Fail => Err("tied column"),
+        }
+    }
+}
+
+// Diagnostic report packed one line per word, column 0 is the leftmost
+// (most significant) bit. Duplicate lines are kept.
+#[derive(Debug, Clone)]
+struct Report {
+    width: usize,
+    words: Vec<u64>,
+}
+
+impl Report {
+    fn parse_report<I: Iterator<Item = String>>(iter: I) -> Result<Report, Box<dyn Error>> {
+        let mut width = None;
+        let words: Vec<u64> =
+            iter.filter(|s| !s.is_empty())
+                .map(|s| -> Result<u64, Box<dyn Error>> {
+                    if *width.get_or_insert(s.len()) != s.len() {
+                        Err(format!("ReportError: bad width {}", s))?
+                    }
+                    if s.len() > 64 {
+                        Err("ReportError: lines wider than 64 bits")?
+                    }
+
+                    s.chars().try_fold(0, |acc, c| match c {
+                        '0' => Ok(acc << 1),
+                        '1' => Ok((acc << 1) | 1),
+                        _ => Err(format!("ReportError: bad digit {}", c).into()),
+                    })
+                })
+                .try_collect()?;
+
+        Ok(Report {width: width.ok_or("empty report")?, words})
     }
 
-    for i in 0..n {
-        let gc = candidate_bit(&gamma_candidates, i, true);
-        gamma_candidates.retain(|s| s.chars().nth(i).unwrap() == gc);
+    fn bit(&self, word: u64, col: usize) -> bool {
+        (word >> (self.width - 1 - col)) & 1 == 1
+    }
 
-        let ec = candidate_bit(&epsilon_candidates, i, false);
-        epsilon_candidates.retain(|s| s.chars().nth(i).unwrap() == ec);
+    fn count_ones<'a, I: Iterator<Item = &'a u64>>(&self, words: I, col: usize) -> usize {
+        words.filter(|&&w| self.bit(w, col)).count()
     }
 
-    assert!(gamma_candidates.len() == 1);
-    assert!(epsilon_candidates.len() == 1);
+    fn column_counts(&self) -> Vec<usize> {
+        (0..self.width)
+            .map(|col| self.count_ones(self.words.iter(), col))
+            .collect()
+    }
 
-    let gamma = i32::from_str_radix(gamma_candidates.iter().nth(0).unwrap().as_str(), 2)?;
-    let epsilon = i32::from_str_radix(epsilon_candidates.iter().nth(0).unwrap().as_str(), 2)?;
+    // Word made of the selected bit of every column
+    fn select_word(&self, criteria: BitCriteria) -> Result<u64, &'static str> {
+        self.column_counts()
+            .into_iter()
+            .try_fold(0, |acc, ones| {
+                criteria.select(ones, self.words.len())
+                        .map(|b| (acc << 1) | b as u64)
+            })
+    }
 
-    println!("gamma: {} epsilon: {} product: {}", gamma, epsilon, gamma * epsilon);
+    fn mask(&self) -> u64 {
+        u64::MAX >> (64 - self.width)
+    }
+}
 
-    Ok (())
+#[derive(Debug, Clone, Copy, Default)]
+struct Node {
+    count: usize,
+    children: [Option<usize>; 2],
 }
 
+// Binary trie of the report words, most significant bit first, where each
+// node counts the words below it
+#[derive(Debug)]
+struct Trie {
+    width: usize,
+    nodes: Vec<Node>,
+}
+
+impl Trie {
+    fn new(report: &Report) -> Trie {
+        let mut nodes = vec![Node::default()];
+        for &word in &report.words {
+            let mut cur = 0;
+            nodes[cur].count += 1;
+            for col in 0..report.width {
+                let bit = report.bit(word, col) as usize;
+                cur = match nodes[cur].children[bit] {
+                    Some(next) => next,
+                    None => {
+                        nodes.push(Node::default());
+                        nodes[cur].children[bit] = Some(nodes.len() - 1);
+                        nodes.len() - 1
+                    }
+                };
+                nodes[cur].count += 1;
+            }
+        }
+
+        Trie {width: report.width, nodes}
+    }
+
+    // Walks down keeping the candidates matching each column's criteria, in
+    // O(width). Once the candidates all agree on a bit it is kept regardless
+    // of the common-bit criteria, fixed bits must always match.
+    fn filter<F: Fn(usize) -> BitCriteria>(&self, criteria: F) -> Result<u64, &'static str> {
+        if self.nodes[0].count == 0 {
+            return Err("no candidates");
+        }
+
+        let mut cur = 0;
+        let mut word = 0;
+        for col in 0..self.width {
+            let node = &self.nodes[cur];
+            let ones = node.children[1].map_or(0, |n| self.nodes[n].count);
+            let bit = match criteria(col) {
+                BitCriteria::Fixed(bit) => bit,
+                _ if ones == 0 || ones == node.count => ones > 0,
+                c => c.select(ones, node.count)?,
+            };
+
+            cur = node.children[bit as usize].ok_or("no candidates")?;
+            word = (word << 1) | bit as u64;
+        }
+
+        Ok(word)
+    }
+
+    fn rating(&self, criteria: BitCriteria) -> Result<u64, &'static str> {
+        self.filter(|_| criteria)
+    }
+}
+
+const USAGE: &str = "Usage: day3 <input> [counts] [tie <one|zero|fail>] [filter <M|L|0|1...>]";
+
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("Failed to read input");
+    if args.len() < 2 {
+        panic!("{}", USAGE);
+    }
+
+    let (mut counts, mut tie, mut spec) = (false, TieBreak::Zero, None);
+    let mut opts = args.iter().skip(2);
+    while let Some(opt) = opts.next() {
+        match opt.as_str() {
+            "counts" => counts = true,
+            "tie" => tie = opts.next().ok_or(USAGE)?.parse()?,
+            "filter" => spec = Some(BitCriteria::parse_spec(opts.next().ok_or(USAGE)?)?),
+            _ => panic!("{}", USAGE),
+        }
+    }
+
+    let file = File::open(&args[1])?;
+    let report =
+        process_results(
+            BufReader::new(file).lines(),
+            |iter| Report::parse_report(iter)
+        )??;
+
+    if counts {
+        let total = report.words.len();
+        for (col, ones) in report.column_counts().iter().enumerate() {
+            println!("column {}: {} ones {} zeros", col, ones, total - ones);
+        }
     }
 
-    part1(&args[1])?;
-    part2(&args[1])?;
+    // Part 1
+    let gamma = report.select_word(BitCriteria::MostCommon(tie))?;
+    let epsilon = !gamma & report.mask();
+    println!("gamma: {} epsilon: {} product: {}", gamma, epsilon, gamma as u128 * epsilon as u128);
+
+    // Part 2
+    let trie = Trie::new(&report);
+    let oxygen = trie.rating(BitCriteria::MostCommon(TieBreak::One))?;
+    let co2 = trie.rating(BitCriteria::LeastCommon(TieBreak::Zero))?;
+    println!("gamma: {} epsilon: {} product: {}", oxygen, co2, oxygen as u128 * co2 as u128);
+
+    // The last criteria carries on to the remaining columns
+    if let Some(spec) = spec {
+        let last = *spec.last().ok_or("empty filter")?;
+        let word = trie.filter(|col| spec.get(col).copied().unwrap_or(last))?;
+        println!("filtered: {:0width$b} ({})", word, word, width = report.width);
+    }
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -1,72 +1,333 @@
-use std::{env, error::Error, fs::File, io::{BufRead, BufReader}};
-use itertools::Itertools;
+use std::{collections::HashMap, env, error::Error, fs::File, io::{BufRead, BufReader}, str::FromStr, time::Instant};
+use itertools::{Itertools, process_results};
+use utils::XorShift;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum WinCondition {
+    Rows,
+    Columns,
+    Diagonals,
+    FullCard,
+    Corners,
+}
+
+impl FromStr for WinCondition {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rows" => Ok(WinCondition::Rows),
+            "cols" => Ok(WinCondition::Columns),
+            "diags" => Ok(WinCondition::Diagonals),
+            "full" => Ok(WinCondition::FullCard),
+            "corners" => Ok(WinCondition::Corners),
+            _ => Err(format!("unknown win condition: {}", s).into()),
+        }
+    }
+}
+
+// Bitset over the cells of a board
+type Cells = Vec<u64>;
+
+fn cells_with(n: usize) -> Cells {
+    vec![0; n.div_ceil(64)]
+}
+
+fn set_cell(bits: &mut Cells, c: usize) {
+    bits[c / 64] |= 1 << (c % 64);
+}
+
+fn has_cell(bits: &Cells, c: usize) -> bool {
+    bits[c / 64] & (1 << (c % 64)) != 0
+}
+
+// Set of cells that wins once every one of them is marked
+#[derive(Debug)]
+struct Line {
+    kind: WinCondition,
+    index: usize,
+    cells: Vec<usize>,
+    mask: Cells,
+}
+
+impl Line {
+    fn is_complete(&self, marked: &Cells) -> bool {
+        self.mask
+            .iter()
+            .zip(marked)
+            .all(|(&m, &b)| b & m == m)
+    }
+}
+
+// Board geometry shared by every board, cells are numbered row by row
+#[derive(Debug)]
+struct Layout {
+    size: usize,
+    lines: Vec<Line>,
+    lines_by_cell: Vec<Vec<usize>>,
+}
+
+impl Layout {
+    // Diagonals only exist on square boards
+    fn new(width: usize, height: usize, conditions: &[WinCondition]) -> Layout {
+        let cell = |x: usize, y: usize| y * width + x;
+        let mut lines = vec![];
+        for &kind in conditions.iter().unique() {
+            let cell_sets: Vec<Vec<usize>> = match kind {
+                WinCondition::Rows => {
+                    (0..height).map(|y| (0..width).map(|x| cell(x, y)).collect()).collect()
+                },
+                WinCondition::Columns => {
+                    (0..width).map(|x| (0..height).map(|y| cell(x, y)).collect()).collect()
+                },
+                WinCondition::Diagonals if width == height => vec![
+                    (0..width).map(|i| cell(i, i)).collect(),
+                    (0..width).map(|i| cell(width - 1 - i, i)).collect(),
+                ],
+                WinCondition::Diagonals => vec![],
+                WinCondition::FullCard => vec![(0..width * height).collect()],
+                WinCondition::Corners => vec![
+                    [cell(0, 0), cell(width - 1, 0), cell(0, height - 1), cell(width - 1, height - 1)]
+                        .into_iter()
+                        .sorted()
+                        .dedup()
+                        .collect()
+                ],
+            };
+
+            lines.extend(
+                cell_sets.into_iter()
+                         .enumerate()
+                         .map(|(index, cells)| {
+                             let mut mask = cells_with(width * height);
+                             cells.iter().for_each(|&c| set_cell(&mut mask, c));
+                             Line {kind, index, cells, mask}
+                         })
+            );
+        }
+
+        let mut lines_by_cell = vec![vec![]; width * height];
+        for (l, line) in lines.iter().enumerate() {
+            line.cells.iter().for_each(|&c| lines_by_cell[c].push(l));
+        }
+
+        Layout {size: width * height, lines, lines_by_cell}
+    }
+}
 
 #[derive(Debug)]
 struct Board {
     grid: Vec<i32>,
-    marked: [i32; 5],
-    scored: bool
+    marked: Cells,
+    unmarked: i64,
+    scored: bool,
 }
 
 impl Board {
-    pub fn parse_board<
-        E: Error,
-        I: Iterator<Item = Result<String, E>>
-    >(it: I) -> Option<Board> {
-        it.flat_map(|r| {
-            r.unwrap()
-             .split_whitespace()
-             .map(&str::parse::<i32>)
-             .collect_vec()
-          })
-          .collect::<Result<Vec<i32>, _>>()
-          .map_or(
-              None, 
-              |v| Some(Board {grid: v, marked: [0, 0, 0, 0, 0], scored: false})
-          )
-    }
-
-    pub fn mark(&mut self, n: i32) -> Option<i32> {
-        if let Some(p) = self.grid.iter().position(|&x| x == n) {
-            self.marked[p/5] |= 1 << (p % 5)
+    fn new(grid: Vec<i32>, layout: &Layout) -> Board {
+        Board {
+            marked: cells_with(layout.size),
+            unmarked: grid.iter().map(|&n| n as i64).sum(),
+            scored: false,
+            grid,
+        }
+    }
+
+    // Marks the given cells, all holding the same number, returning the first
+    // line completed. Only lines through those cells need checking.
+    pub fn mark<I: Iterator<Item = usize>>(&mut self, cells: I, layout: &Layout) -> Option<usize> {
+        let cells =
+            cells.filter(|&c| !has_cell(&self.marked, c))
+                 .collect_vec();
+        for &c in &cells {
+            set_cell(&mut self.marked, c);
+            self.unmarked -= self.grid[c] as i64;
         }
 
-        let bingo =
-            self.marked.contains(&0x1f) ||
-            self.marked.iter()
-                       .fold(0x1f, |acc, x| acc & x) != 0;
+        let completed =
+            cells.iter()
+                 .flat_map(|&c| &layout.lines_by_cell[c])
+                 .copied()
+                 .filter(|&l| layout.lines[l].is_complete(&self.marked))
+                 .min();
 
-        if bingo {
+        if completed.is_some() {
             self.scored = true;
-            let s =
-                self.grid.iter()
-                        .enumerate()
-                        .filter(|&(p, _)| {
-                            self.marked[p/5] & (1 << (p % 5)) == 0
-                        })
-                        .map(|(_, n)| n)
-                        .sum::<i32>();
-            
-            Some(s * n)
-        } else {
-            None
         }
+        completed
     }
 
     pub fn is_playing(&self) -> bool {
         !self.scored
     }
+}
 
-    pub fn reset(&mut self) {
-        self.scored = false;
-        self.marked.iter_mut().for_each(|x| *x = 0);
+#[derive(Debug)]
+struct Win {
+    draw: usize,
+    number: i32,
+    board: usize,
+    line: usize,
+    unmarked: i64,
+    score: i64,
+}
+
+#[derive(Debug)]
+struct Bingo {
+    layout: Layout,
+    boards: Vec<Board>,
+    // Every (board, cell) holding a number, in board order
+    index: HashMap<i32, Vec<(usize, usize)>>,
+}
+
+impl Bingo {
+    // Boards are blocks of whitespace separated rows, split by blank lines
+    fn parse_boards<I: Iterator<Item = String>>(iter: I, conditions: &[WinCondition])
+        -> Result<Bingo, Box<dyn Error>>
+    {
+        let grids: Vec<Vec<Vec<i32>>> =
+            iter.group_by(|s| s.trim().is_empty())
+                .into_iter()
+                .filter(|(blank, _)| !blank)
+                .map(|(_, rows)| {
+                    rows.map(|r| {
+                            r.split_whitespace()
+                             .map(&str::parse::<i32>)
+                             .collect::<Result<Vec<_>, _>>()
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .try_collect()?;
+
+        let first = grids.first().ok_or("no boards")?;
+        let (width, height) = (first[0].len(), first.len());
+        if width == 0 {
+            Err("empty board")?
+        }
+
+        let layout = Layout::new(width, height, conditions);
+        let boards =
+            grids.into_iter()
+                 .map(|g| {
+                     if g.len() != height || g.iter().any(|r| r.len() != width) {
+                         Err(format!("board is not {}x{}", width, height))
+                     } else {
+                         Ok(Board::new(g.concat(), &layout))
+                     }
+                 })
+                 .try_collect()?;
+
+        Ok(Bingo::new(layout, boards))
+    }
+
+    fn new(layout: Layout, boards: Vec<Board>) -> Bingo {
+        let mut index: HashMap<i32, Vec<(usize, usize)>> = HashMap::new();
+        for (b, board) in boards.iter().enumerate() {
+            for (c, &n) in board.grid.iter().enumerate() {
+                index.entry(n).or_default().push((b, c));
+            }
+        }
+
+        Bingo {layout, boards, index}
+    }
+
+    // Every win in the order it happens, boards stop playing once they win.
+    // Only boards holding each drawn number are visited.
+    fn play(&mut self, seq: &[i32]) -> Vec<Win> {
+        let mut wins = vec![];
+        for (draw, &n) in seq.iter().enumerate() {
+            let hits = match self.index.get(&n) {
+                Some(hits) => hits,
+                None => continue,
+            };
+
+            for (i, cells) in &hits.iter().group_by(|&&(b, _)| b) {
+                let b = &mut self.boards[i];
+                if !b.is_playing() {
+                    continue
+                }
+                if let Some(line) = b.mark(cells.map(|&(_, c)| c), &self.layout) {
+                    let unmarked = b.unmarked;
+                    wins.push(Win {draw, number: n, board: i, line, unmarked, score: unmarked * n as i64});
+                }
+            }
+        }
+        wins
+    }
+
+    fn never_won(&self) -> Vec<usize> {
+        self.boards
+            .iter()
+            .positions(Board::is_playing)
+            .collect()
+    }
+
+    fn describe(&self, win: &Win) -> String {
+        let line = &self.layout.lines[win.line];
+        let numbers = line.cells.iter().map(|&c| self.boards[win.board].grid[c]).join(" ");
+        format!("board {} on draw {} ({}), {:?} {} [{}], unmarked {}, score {}",
+                win.board, win.draw + 1, win.number, line.kind, line.index, numbers, win.unmarked, win.score)
+    }
+}
+
+// Random 5x5 boards against a shuffled draw of every number in 0..draws
+fn bench(num_boards: usize, draws: usize, conditions: &[WinCondition]) {
+    let mut rng = XorShift(0x2545f4914f6cdd1d);
+    let range = draws.max(1);
+    let layout = Layout::new(5, 5, conditions);
+    let boards =
+        (0..num_boards)
+            .map(|_| Board::new((0..25).map(|_| rng.below(range) as i32).collect(), &layout))
+            .collect_vec();
+
+    let mut seq = (0..draws as i32).collect_vec();
+    for i in (1..seq.len()).rev() {
+        seq.swap(i, rng.below(i + 1));
     }
+
+    let start = Instant::now();
+    let mut bingo = Bingo::new(layout, boards);
+    let indexed = start.elapsed();
+    let wins = bingo.play(&seq);
+    let played = start.elapsed() - indexed;
+
+    let hits: usize = seq.iter().filter_map(|n| bingo.index.get(n)).map(Vec::len).sum();
+    println!("Boards: {} Draws: {} Hits: {} Wins: {}", num_boards, draws, hits, wins.len());
+    println!("Indexing: {:?} Playing: {:?} ({:.1} ns/hit)",
+             indexed, played, played.as_nanos() as f64 / hits.max(1) as f64);
 }
 
+const USAGE: &str =
+    "Usage: day4 <input> [rows,cols,diags,full,corners] [report]\n       day4 bench <boards> <draws> [rows,cols,diags,full,corners]";
+
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("Failed to read input");
+    if args.len() < 2 {
+        panic!("{}", USAGE);
+    }
+
+    let parse_conditions = |s: &str| -> Result<Vec<WinCondition>, Box<dyn Error>> {
+        s.split(',').map(&str::parse::<WinCondition>).try_collect()
+    };
+    let mut conditions = vec![WinCondition::Rows, WinCondition::Columns];
+
+    if args[1] == "bench" {
+        if args.len() < 4 || args.len() > 5 {
+            panic!("{}", USAGE);
+        }
+        if let Some(s) = args.get(4) {
+            conditions = parse_conditions(s)?;
+        }
+        bench(args[2].parse()?, args[3].parse()?, &conditions);
+        return Ok(());
+    }
+
+    let mut report = false;
+    for opt in &args[2..] {
+        match opt.as_str() {
+            "report" => report = true,
+            s => conditions = parse_conditions(s)?,
+        }
     }
 
     let file = File::open(&args[1])?;
@@ -74,44 +335,38 @@ fn main() -> Result<(), Box<dyn Error>> {
     let seq =
         file_iter
             .next()
-            .unwrap()?
+            .ok_or("no draws")??
             .split(',')
             .map(&str::parse::<i32>)
             .collect::<Result<Vec<_>,_>>()?;
-    
-    let mut boards =
-        file_iter
-            .chunks(6)
-            .into_iter()
-            .filter_map(Board::parse_board)
-            .collect_vec();
-    
-    // Part 1
-    let mut bingo_score = -1;
-    'outer: for &n in &seq {
-        for b in &mut boards {
-            match b.mark(n) {
-                Some(score) => {
-                    bingo_score = score;
-                    break 'outer
-                },
-                None => ()
-            }
+
+    let mut bingo =
+        process_results(
+            file_iter,
+            |iter| Bingo::parse_boards(iter, &conditions)
+        )??;
+    let wins = bingo.play(&seq);
+
+    if report {
+        for (rank, w) in wins.iter().enumerate() {
+            println!("{}: {}", rank + 1, bingo.describe(w));
+        }
+        let never = bingo.never_won();
+        if !never.is_empty() {
+            println!("Never won: {}", never.iter().join(", "));
         }
     }
 
-    println!("First bingo score: {}", bingo_score);
-    boards.iter_mut().for_each(Board::reset);
+    let show = |name: &str, win: Option<&Win>| match win {
+        Some(w) => println!("{} bingo score: {} ({})", name, w.score, bingo.describe(w)),
+        None => println!("{} bingo score: none", name),
+    };
+
+    // Part 1
+    show("First", wins.first());
 
     // Part 2
-    bingo_score = -1;
-    for &n in &seq {
-        boards.iter_mut().for_each(|b| {
-            bingo_score = b.mark(n).unwrap_or(bingo_score);
-        });
-        boards.retain(Board::is_playing);
-    }
-    println!("Last bingo score: {}", bingo_score);
+    show("Last", wins.last());
 
     Ok(())
 }
@@ -0,0 +1,221 @@
+use std::{collections::HashSet, error::Error, fmt, str::FromStr};
+
+use itertools::Itertools;
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+#[derive(Hash)]
+pub struct Pos {
+    pub x: i32,
+    pub y: i32
+}
+
+impl FromStr for Pos {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let p: Vec<i32> =
+            s.split(',')
+             .map(&str::parse::<i32>)
+             .try_collect()?;
+
+        if p.len() != 2 {
+            Err(format!("PosError: {}", s))?
+        }
+
+        Ok(Pos {x: p[0], y: p[1]})
+    }
+}
+
+impl fmt::Display for Pos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{}", self.x, self.y)
+    }
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 { a.abs() } else { gcd(b, a % b) }
+}
+
+// Deltas take 33 bits, so their products are only safe in i128
+fn cross(ax: i64, ay: i64, bx: i64, by: i64) -> i128 {
+    ax as i128 * by as i128 - ay as i128 * bx as i128
+}
+
+// Difference of two coordinates, widened first so it cannot overflow
+fn diff(a: i32, b: i32) -> i64 {
+    a as i64 - b as i64
+}
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+pub struct Line {
+    pub start: Pos,
+    pub end: Pos
+}
+
+impl FromStr for Line {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let p: Vec<Pos> =
+            s.split(" -> ")
+             .map(&str::parse::<Pos>)
+             .try_collect()?;
+
+        if p.len() != 2 {
+            Err(format!("LineError: {}", s))?
+        }
+
+        Ok(Line {start: p[0], end: p[1]})
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {}", self.start, self.end)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Horizontal,
+    Vertical,
+    Diagonal,
+    Other,
+}
+
+impl FromStr for Orientation {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "horizontal" => Ok(Orientation::Horizontal),
+            "vertical" => Ok(Orientation::Vertical),
+            "diagonal" => Ok(Orientation::Diagonal),
+            "other" => Ok(Orientation::Other),
+            _ => Err(format!("OrientationError: {}", s).into()),
+        }
+    }
+}
+
+impl Line {
+    // Diagonal means 45 degrees, a single point counts as horizontal
+    pub fn orientation(&self) -> Orientation {
+        let (dx, dy) = self.delta();
+        if dy == 0 {
+            Orientation::Horizontal
+        } else if dx == 0 {
+            Orientation::Vertical
+        } else if dx.abs() == dy.abs() {
+            Orientation::Diagonal
+        } else {
+            Orientation::Other
+        }
+    }
+
+    pub fn contains(&self, p: Pos) -> bool {
+        self.index_of(p).is_some()
+    }
+
+    fn delta(&self) -> (i64, i64) {
+        (diff(self.end.x, self.start.x), diff(self.end.y, self.start.y))
+    }
+
+    // Smallest lattice step along the line, and how many steps it takes
+    fn step(&self) -> ((i64, i64), i64) {
+        let (dx, dy) = self.delta();
+        match gcd(dx, dy) {
+            0 => ((0, 0), 0),
+            g => ((dx / g, dy / g), g),
+        }
+    }
+
+    fn at(&self, k: i64) -> Pos {
+        let ((sx, sy), _) = self.step();
+        Pos {x: (self.start.x as i64 + k * sx) as i32, y: (self.start.y as i64 + k * sy) as i32}
+    }
+
+    // Every lattice point on the line, of any slope, start to end
+    pub fn points(&self) -> impl Iterator<Item = Pos> + '_ {
+        let (_, count) = self.step();
+        (0..=count).map(move |k| self.at(k))
+    }
+
+    // Index k with `p == self.at(k)`, if p is a lattice point of the line
+    fn index_of(&self, p: Pos) -> Option<i64> {
+        let ((sx, sy), count) = self.step();
+        let (vx, vy) = (diff(p.x, self.start.x), diff(p.y, self.start.y));
+        let k =
+            if count == 0 {
+                0
+            } else if sx != 0 {
+                vx / sx
+            } else {
+                vy / sy
+            };
+
+        if (0..=count).contains(&k) && vx == k * sx && vy == k * sy {
+            Some(k)
+        } else {
+            None
+        }
+    }
+
+    // Lattice points shared with another line, found analytically
+    pub fn intersection(&self, other: &Line) -> Vec<Pos> {
+        let (r, s) = (self.delta(), other.delta());
+        let (ox, oy) = (diff(other.start.x, self.start.x), diff(other.start.y, self.start.y));
+        let den = cross(r.0, r.1, s.0, s.1);
+
+        if den != 0 {
+            // Crossing lines meet in at most one point, at self.start + t * r
+            let (t, u) = (cross(ox, oy, s.0, s.1), cross(ox, oy, r.0, r.1));
+            let (t, u, den) = if den < 0 { (-t, -u, -den) } else { (t, u, den) };
+            if !(0..=den).contains(&t) || !(0..=den).contains(&u) {
+                return vec![];
+            }
+            let (rx, ry) = (r.0 as i128, r.1 as i128);
+            if (t * rx) % den != 0 || (t * ry) % den != 0 {
+                return vec![];
+            }
+
+            let p = Pos {
+                x: (self.start.x as i128 + t * rx / den) as i32,
+                y: (self.start.y as i128 + t * ry / den) as i32,
+            };
+            return vec![p];
+        }
+
+        // Parallel, or one of them is a single point
+        if r == (0, 0) {
+            return other.index_of(self.start).map(|_| self.start).into_iter().collect();
+        }
+        if s == (0, 0) {
+            return self.index_of(other.start).map(|_| other.start).into_iter().collect();
+        }
+        if cross(ox, oy, r.0, r.1) != 0 {
+            return vec![];
+        }
+
+        // Collinear. Any lattice point on the same line as a primitive step is
+        // a whole number of steps away, so both lines index the same lattice.
+        let ((sx, sy), count) = self.step();
+        let to_k = |p: Pos| {
+            let (vx, vy) = (diff(p.x, self.start.x), diff(p.y, self.start.y));
+            if sx != 0 { vx / sx } else { vy / sy }
+        };
+        let (a, b) = (to_k(other.start), to_k(other.end));
+        let (lo, hi) = (a.min(b).max(0), a.max(b).min(count));
+        (lo..=hi).map(|k| self.at(k)).collect()
+    }
+}
+
+// Points covered by at least two lines, from pairwise intersections
+pub fn overlap_points(lines: &[&Line]) -> HashSet<Pos> {
+    lines.iter()
+         .tuple_combinations()
+         .flat_map(|(a, b)| a.intersection(b))
+         .collect()
+}
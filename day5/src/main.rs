@@ -1,89 +1,46 @@
-use std::{collections::HashMap, env, error::Error, fs::File, io::{BufRead, BufReader}, str::FromStr};
+use std::{env, error::Error, fs::File, io::{BufRead, BufReader, BufWriter}};
 
 use itertools::Itertools;
 
-#[derive(Debug)]
-#[derive(Clone, Copy)]
-#[derive(PartialEq, Eq)]
-#[derive(Hash)]
-struct Pos {
-    x: i32,
-    y: i32
-}
-
-impl FromStr for Pos {
-    type Err = Box<dyn Error>;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let p: Vec<i32> = 
-            s.split(',')
-             .map(&str::parse::<i32>)
-             .try_collect()?;
-        
-        if p.len() != 2 {
-            Err(format!("PosError: {}", s))?
-        }
+mod geometry;
+mod raster;
 
-        return Ok(Pos {x: p[0], y: p[1]})
-    }
-}
+use geometry::{Line, Orientation, Pos, overlap_points};
+use raster::{Bounds, VentMap};
 
-#[derive(Debug)]
-#[derive(Clone, Copy)]
-struct Line {
-    start: Pos,
-    end: Pos
-}
-
-impl FromStr for Line {
-    type Err = Box<dyn Error>;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let p: Vec<Pos> =
-            s.split(" -> ")
-             .map(&str::parse::<Pos>)
-             .try_collect()?;
-
-        if p.len() != 2 {
-            Err(format!("LineError: {}", s))?
-        }
+const USAGE: &str = "Usage: day5 <input> [raster] [heatmap <file>] [image <file.pgm>] \
+                     [min <k>] [rect <x0,y0,x1,y1>] [only <horizontal,vertical,diagonal,other>] [at <x,y>]";
 
-        Ok(Line {start: p[0], end: p[1]})
-    }
-}
-
-impl Line {
-    pub fn is_horz_vert(&self) -> bool {
-        self.start.x == self.end.x ||
-        self.start.y == self.end.y
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        panic!("{}", USAGE);
     }
 
-    pub fn mark(&self, v: &mut HashMap<Pos, i32>) {
-        let dy = self.end.y - self.start.y;
-        let dx = self.end.x - self.start.x;
-        let (slope_x, slope_y, count) =
-            if self.end.x != self.start.x {
-                (dx.signum(), dy/dx.abs(), dx.abs() + 1)
-            } else if self.end.y != self.start.y {
-                (0, dy.signum(), dy.abs() + 1)
-            } else {
-                (0, 0, 0)
-            };
-
-        let mut pos = self.start.clone();
-        for _ in 0..count {
-            *v.entry(pos).or_insert(0) += 1;
-            pos.x += slope_x;
-            pos.y += slope_y;
+    let (mut raster, mut heatmap, mut image) = (false, None, None);
+    let (mut min, mut rect, mut only, mut at) = (None, None, None, None);
+    let mut opts = args.iter().skip(2);
+    while let Some(opt) = opts.next() {
+        let mut value = || opts.next().ok_or(USAGE);
+        match opt.as_str() {
+            "raster" => raster = true,
+            "heatmap" => heatmap = Some(value()?),
+            "image" => image = Some(value()?),
+            "min" => {
+                let k = value()?.parse::<u16>()?;
+                if k == 0 {
+                    return Err("min must be at least 1".into());
+                }
+                min = Some(k)
+            },
+            "rect" => rect = Some(value()?.parse::<Bounds>()?),
+            "only" => {
+                only = Some(value()?.split(',').map(&str::parse::<Orientation>).collect::<Result<Vec<_>, _>>()?)
+            },
+            "at" => at = Some(value()?.parse::<Pos>()?),
+            _ => panic!("{}", USAGE),
         }
     }
-}
-
-fn main() -> Result<(), Box<dyn Error>> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("Failed to read input");
-    }
 
     let file = File::open(&args[1])?;
     let lines: Vec<Line> =
@@ -95,30 +52,59 @@ fn main() -> Result<(), Box<dyn Error>> {
             })
             .try_collect()?;
     
+    // Rasterising every point is kept around to cross-check the analytic count
+    let count_overlaps = |lines: &[&Line]| {
+        if raster {
+            VentMap::from_lines(lines).overlaps(2, None)
+        } else {
+            overlap_points(lines).len()
+        }
+    };
+    let of_classes = |classes: &[Orientation]| {
+        lines.iter()
+             .filter(|l| classes.contains(&l.orientation()))
+             .collect_vec()
+    };
+
     // Part 1
-    let mut counts: HashMap<Pos, i32> = HashMap::new();
-    lines.iter()
-         .filter(|&l| l.is_horz_vert())
-         .for_each(|l| l.mark(&mut counts));
-    
-    let overlaps =
-        counts.iter()
-              .filter(|&(_, &count)| count > 1)
-              .count();
-    
-    println!("Overlaps: {}", overlaps);
-    
+    let horz_vert = of_classes(&[Orientation::Horizontal, Orientation::Vertical]);
+    println!("Overlaps: {}", count_overlaps(&horz_vert));
+
     // Part 2
-    counts.clear();
-    lines.iter()
-         .for_each(|l| l.mark(&mut counts));
-    
-    let overlaps_diag =
-        counts.iter()
-              .filter(|&(_, &count)| count > 1)
-              .count();
-    
-    println!("Overlaps_diag: {}", overlaps_diag);
+    let all = lines.iter().collect_vec();
+    println!("Overlaps_diag: {}", count_overlaps(&all));
+
+    if min.is_none() && rect.is_none() && only.is_none() && at.is_none() && heatmap.is_none() && image.is_none() {
+        return Ok(());
+    }
+
+    // Queries and exports only consider the selected orientations
+    let selected = only.as_deref().map_or_else(|| all.clone(), of_classes);
+    let map = VentMap::from_lines(&selected);
+
+    if min.is_some() || rect.is_some() || only.is_some() {
+        let k = min.unwrap_or(2);
+        println!("Points with at least {} lines: {}", k, map.overlaps(k, rect));
+    }
+
+    if let Some(p) = at {
+        println!("Lines through {}: {}", p, map.get(p));
+        // Numbered by line of the input
+        let through =
+            lines.iter()
+                 .enumerate()
+                 .filter(|(_, l)| only.as_deref().is_none_or(|c| c.contains(&l.orientation())) && l.contains(p));
+        for (i, line) in through {
+            println!("  {}: {} ({:?})", i + 1, line, line.orientation());
+        }
+    }
+
+    if let Some(path) = heatmap {
+        map.write_text(BufWriter::new(File::create(path)?))?;
+    }
+    if let Some(path) = image {
+        map.write_pgm(BufWriter::new(File::create(path)?))?;
+    }
 
     Ok(())
 }
@@ -0,0 +1,171 @@
+use std::{collections::HashMap, error::Error, io::Write, str::FromStr};
+
+use utils::Grid;
+
+use crate::geometry::{Line, Pos};
+
+// Largest map, in cells, that is rasterised into a dense grid
+const MAX_DENSE_AREA: i64 = 1 << 24;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Bounds {
+    pub min: Pos,
+    pub max: Pos,
+}
+
+impl Bounds {
+    fn of(lines: &[&Line]) -> Option<Bounds> {
+        let points = lines.iter().flat_map(|l| [l.start, l.end]);
+        let (xs, ys): (Vec<i32>, Vec<i32>) = points.map(|p| (p.x, p.y)).unzip();
+        Some(Bounds {
+            min: Pos {x: *xs.iter().min()?, y: *ys.iter().min()?},
+            max: Pos {x: *xs.iter().max()?, y: *ys.iter().max()?},
+        })
+    }
+
+    pub fn width(&self) -> i64 {
+        self.max.x as i64 - self.min.x as i64 + 1
+    }
+
+    pub fn height(&self) -> i64 {
+        self.max.y as i64 - self.min.y as i64 + 1
+    }
+
+    pub fn contains(&self, p: Pos) -> bool {
+        (self.min.x..=self.max.x).contains(&p.x) && (self.min.y..=self.max.y).contains(&p.y)
+    }
+}
+
+// Rectangle from two opposite corners, `x0,y0,x1,y1`
+impl FromStr for Bounds {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let v: Vec<i32> = s.split(',').map(&str::parse::<i32>).collect::<Result<_, _>>()?;
+        if v.len() != 4 {
+            Err(format!("BoundsError: {}", s))?
+        }
+
+        Ok(Bounds {
+            min: Pos {x: v[0].min(v[2]), y: v[1].min(v[3])},
+            max: Pos {x: v[0].max(v[2]), y: v[1].max(v[3])},
+        })
+    }
+}
+
+// Number of lines through each point, dense when the bounds are small enough
+#[derive(Debug)]
+pub enum VentMap {
+    Dense {bounds: Bounds, grid: Grid<u16>},
+    Sparse {bounds: Bounds, counts: HashMap<Pos, u16>},
+}
+
+impl VentMap {
+    pub fn from_lines(lines: &[&Line]) -> VentMap {
+        let origin = Pos {x: 0, y: 0};
+        let bounds = Bounds::of(lines).unwrap_or(Bounds {min: origin, max: origin});
+
+        let mut map =
+            if bounds.width().saturating_mul(bounds.height()) <= MAX_DENSE_AREA {
+                let grid = Grid::filled(bounds.width() as usize, bounds.height() as usize, 0);
+                VentMap::Dense {bounds, grid}
+            } else {
+                VentMap::Sparse {bounds, counts: HashMap::new()}
+            };
+
+        for line in lines {
+            line.points().for_each(|p| map.bump(p));
+        }
+        map
+    }
+
+    fn bump(&mut self, p: Pos) {
+        let count = match self {
+            VentMap::Dense {bounds, grid} => {
+                grid.index_mut((p.x - bounds.min.x) as usize, (p.y - bounds.min.y) as usize)
+            },
+            VentMap::Sparse {counts, ..} => counts.entry(p).or_insert(0),
+        };
+        *count = count.saturating_add(1);
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        match self {
+            VentMap::Dense {bounds, ..} | VentMap::Sparse {bounds, ..} => *bounds,
+        }
+    }
+
+    pub fn get(&self, p: Pos) -> u16 {
+        match self {
+            VentMap::Dense {bounds, grid} => {
+                let (x, y) = ((p.x - bounds.min.x) as usize, (p.y - bounds.min.y) as usize);
+                grid.get(x, y).copied().unwrap_or(0)
+            },
+            VentMap::Sparse {counts, ..} => counts.get(&p).copied().unwrap_or(0),
+        }
+    }
+
+    // Points crossed by at least `k` lines, `k` must be positive, optionally
+    // only those inside `region`
+    pub fn overlaps(&self, k: u16, region: Option<Bounds>) -> usize {
+        self.points()
+            .filter(|&(p, c)| c >= k && region.is_none_or(|r| r.contains(p)))
+            .count()
+    }
+
+    fn check_exportable(&self) -> Result<Bounds, Box<dyn Error>> {
+        let bounds = self.bounds();
+        if bounds.width().saturating_mul(bounds.height()) > MAX_DENSE_AREA {
+            Err("vent map too large to export")?
+        }
+        Ok(bounds)
+    }
+
+    // One character per point: `.` for none, the count up to 9, `#` above that
+    pub fn write_text<W: Write>(&self, mut w: W) -> Result<(), Box<dyn Error>> {
+        let bounds = self.check_exportable()?;
+        for y in bounds.min.y..=bounds.max.y {
+            let row: String =
+                (bounds.min.x..=bounds.max.x)
+                    .map(|x| match self.get(Pos {x, y}) {
+                        0 => '.',
+                        c @ 1..=9 => char::from(b'0' + c as u8),
+                        _ => '#',
+                    })
+                    .collect();
+            writeln!(w, "{}", row)?;
+        }
+        Ok(())
+    }
+
+    // Binary greyscale PGM, brightness proportional to the count
+    pub fn write_pgm<W: Write>(&self, mut w: W) -> Result<(), Box<dyn Error>> {
+        let bounds = self.check_exportable()?;
+        let max = self.points().map(|(_, c)| c).max().unwrap_or(0).max(1) as u32;
+
+        writeln!(w, "P5\n{} {}\n255", bounds.width(), bounds.height())?;
+        for y in bounds.min.y..=bounds.max.y {
+            let row: Vec<u8> =
+                (bounds.min.x..=bounds.max.x)
+                    .map(|x| (self.get(Pos {x, y}) as u32 * 255 / max) as u8)
+                    .collect();
+            w.write_all(&row)?;
+        }
+        Ok(())
+    }
+
+    // Every point crossed by at least one line, with its count
+    pub fn points(&self) -> Box<dyn Iterator<Item = (Pos, u16)> + '_> {
+        match self {
+            VentMap::Dense {bounds, grid} => Box::new(
+                grid.iter_coords()
+                    .flatten()
+                    .filter(|&(&c, _)| c > 0)
+                    .map(move |(&c, (x, y))| {
+                        (Pos {x: x as i32 + bounds.min.x, y: y as i32 + bounds.min.y}, c)
+                    })
+            ),
+            VentMap::Sparse {counts, ..} => Box::new(counts.iter().map(|(&p, &c)| (p, c))),
+        }
+    }
+}
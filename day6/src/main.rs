@@ -1,45 +1,279 @@
-use std::{env, fs::File, io::{BufRead, BufReader}, error::Error};
+use std::{env, fs::{self, File}, io::{BufRead, BufReader, BufWriter, Write}, error::Error, str::FromStr};
 
-fn step(ages: &mut [u64; 9]) {
-    let regen = ages[0];
-    (1..9).for_each(|n| ages[n - 1] = ages[n]);
-    ages[8] = 0;
-    ages[6] += regen;
-    ages[8] += regen;
+use itertools::Itertools;
+
+const RESET_TIMER: usize = 6;
+const NEWBORN_TIMER: usize = 8;
+
+// The series has a row per day, so it stops being useful long before the
+// matrix power does
+const MAX_SERIES_DAYS: u64 = 100_000;
+
+#[derive(Debug, Clone, Copy)]
+enum Arith {
+    Exact,
+    Modular(u64),
+}
+
+impl Arith {
+    fn reduce(self, a: u64) -> u64 {
+        match self {
+            Arith::Exact => a,
+            Arith::Modular(m) => a % m,
+        }
+    }
+
+    fn add(self, a: u64, b: u64) -> Result<u64, &'static str> {
+        match self {
+            Arith::Exact => a.checked_add(b).ok_or("overflow, try a modulus"),
+            Arith::Modular(m) => Ok(((a as u128 + b as u128) % m as u128) as u64),
+        }
+    }
+
+    fn mul(self, a: u64, b: u64) -> Result<u64, &'static str> {
+        match self {
+            Arith::Exact => a.checked_mul(b).ok_or("overflow, try a modulus"),
+            Arith::Modular(m) => Ok(((a as u128 * b as u128) % m as u128) as u64),
+        }
+    }
+}
+
+// Square matrix acting on the column vector of timer buckets
+#[derive(Debug, Clone)]
+struct Matrix {
+    n: usize,
+    data: Vec<u64>,
+}
+
+impl Matrix {
+    // m[i][j] is the number of fish with timer i one day after a single fish
+    // with timer j
+    fn lanternfish(reset: usize, newborn: usize) -> Matrix {
+        let n = reset.max(newborn) + 1;
+        let mut m = Matrix {n, data: vec![0; n * n]};
+        (1..n).for_each(|j| *m.at_mut(j - 1, j) += 1);
+        *m.at_mut(reset, 0) += 1;
+        *m.at_mut(newborn, 0) += 1;
+        m
+    }
+
+    fn at(&self, i: usize, j: usize) -> u64 {
+        self.data[i * self.n + j]
+    }
+
+    fn at_mut(&mut self, i: usize, j: usize) -> &mut u64 {
+        &mut self.data[i * self.n + j]
+    }
+
+    fn mul(&self, other: &Matrix, arith: Arith) -> Result<Matrix, &'static str> {
+        let n = self.n;
+        let mut out = Matrix {n, data: vec![0; n * n]};
+        for i in 0..n {
+            for j in 0..n {
+                *out.at_mut(i, j) =
+                    (0..n).try_fold(0, |acc, k| {
+                        arith.mul(self.at(i, k), other.at(k, j))
+                             .and_then(|v| arith.add(acc, v))
+                    })?;
+            }
+        }
+        Ok(out)
+    }
+
+    fn apply(&self, v: &[u64], arith: Arith) -> Result<Vec<u64>, &'static str> {
+        (0..self.n)
+            .map(|i| {
+                v.iter()
+                 .enumerate()
+                 .try_fold(0, |acc, (j, &x)| {
+                     arith.mul(self.at(i, j), x)
+                          .and_then(|y| arith.add(acc, y))
+                 })
+            })
+            .collect()
+    }
+}
+
+// Advance the buckets by `days` using O(log days) matrix squarings
+fn fast_forward(ages: &[u64], days: u64, reset: usize, newborn: usize, arith: Arith)
+    -> Result<Vec<u64>, &'static str>
+{
+    let mut base = Matrix::lanternfish(reset, newborn);
+    if ages.len() != base.n {
+        return Err("wrong number of buckets");
+    }
+
+    // Powers of the same matrix commute, so the bits can be applied in any order
+    let mut v = ages.iter().map(|&x| arith.reduce(x)).collect::<Vec<_>>();
+    let mut e = days;
+    while e > 0 {
+        if e & 1 == 1 {
+            v = base.apply(&v, arith)?;
+        }
+        e >>= 1;
+        if e > 0 {
+            base = base.mul(&base, arith)?;
+        }
+    }
+
+    Ok(v)
+}
+
+fn total(ages: &[u64], arith: Arith) -> Result<u64, &'static str> {
+    ages.iter().try_fold(0, |acc, &x| arith.add(acc, x))
+}
+
+#[derive(Debug, Clone)]
+struct LanternfishModel {
+    cycle: usize,
+    newborn_delay: usize,
+    days: Vec<u64>,
+    arith: Arith,
+    series: Option<String>,
+}
+
+impl Default for LanternfishModel {
+    fn default() -> Self {
+        LanternfishModel {
+            cycle: RESET_TIMER + 1,
+            newborn_delay: NEWBORN_TIMER - RESET_TIMER,
+            days: vec![80, 256],
+            arith: Arith::Exact,
+            series: None,
+        }
+    }
+}
+
+// Config is one `key = value` per line, `#` starts a comment:
+//   cycle = 7
+//   newborn_delay = 2
+//   days = 80, 256
+//   modulus = 1000000007
+//   series = growth.csv
+impl FromStr for LanternfishModel {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut model = LanternfishModel::default();
+        for line in s.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue
+            }
+
+            let (key, value) =
+                line.split_once('=')
+                    .map(|(k, v)| (k.trim(), v.trim()))
+                    .ok_or(format!("ConfigError: {}", line))?;
+            match key {
+                "cycle" => model.cycle = value.parse()?,
+                "newborn_delay" => model.newborn_delay = value.parse()?,
+                "days" => {
+                    model.days =
+                        value.split(',')
+                             .map(|d| d.trim().parse::<u64>())
+                             .collect::<Result<Vec<_>, _>>()?
+                },
+                "modulus" => {
+                    model.arith = match value.parse::<u64>()? {
+                        0 => Err("modulus must be positive")?,
+                        m => Arith::Modular(m),
+                    }
+                },
+                "series" => model.series = Some(value.to_string()),
+                _ => Err(format!("ConfigError: unknown key {}", key))?
+            }
+        }
+
+        if model.cycle == 0 {
+            Err("cycle must be positive")?
+        }
+        let last = model.days.iter().copied().max().unwrap_or(0);
+        if model.series.is_some() && last > MAX_SERIES_DAYS {
+            Err(format!("series is limited to {} days, got {}", MAX_SERIES_DAYS, last))?
+        }
+
+        Ok(model)
+    }
+}
+
+impl LanternfishModel {
+    fn reset(&self) -> usize {
+        self.cycle - 1
+    }
+
+    fn newborn(&self) -> usize {
+        self.cycle - 1 + self.newborn_delay
+    }
+
+    fn parse_ages(&self, s: &str) -> Result<Vec<u64>, Box<dyn Error>> {
+        let mut ages = vec![0; self.newborn() + 1];
+        s.split(',')
+         .map(&str::parse::<usize>)
+         .try_for_each(|r| -> Result<(), Box<dyn Error>> {
+             let n = r?;
+             *ages.get_mut(n).ok_or("timer out of range")? += 1;
+             Ok(())
+         })?;
+        Ok(ages)
+    }
+
+    fn population(&self, ages: &[u64], days: u64) -> Result<u64, &'static str> {
+        let v = fast_forward(ages, days, self.reset(), self.newborn(), self.arith)?;
+        total(&v, self.arith)
+    }
+
+    // CSV of every bucket count from day 0 up to the last query day
+    fn write_series<W: Write>(&self, ages: &[u64], mut w: W) -> Result<(), Box<dyn Error>> {
+        let m = Matrix::lanternfish(self.reset(), self.newborn());
+        let last = self.days.iter().copied().max().unwrap_or(0);
+
+        let header = (0..ages.len()).map(|t| format!("t{}", t)).join(",");
+        writeln!(w, "day,{},total", header)?;
+
+        let mut v = ages.iter().map(|&x| self.arith.reduce(x)).collect_vec();
+        for day in 0..=last {
+            if day > 0 {
+                v = m.apply(&v, self.arith)?;
+            }
+            writeln!(w, "{},{},{}", day, v.iter().join(","), total(&v, self.arith)?)?;
+        }
+
+        Ok(())
+    }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("Failed to read input");
+    if args.len() < 2 || args.len() > 3 {
+        panic!("Usage: day6 <input> [config]");
     }
 
+    let model = match args.get(2) {
+        Some(path) => fs::read_to_string(path)?.parse::<LanternfishModel>()?,
+        None => LanternfishModel::default(),
+    };
+
     let file = File::open(&args[1])?;
-    let mut ages = [0; 9];
-    BufReader::new(file)
-        .lines()
-        .next()
-        .ok_or("Read Error")??
-        .split(',')
-        .map(&str::parse::<usize>)
-        .try_for_each(|r| {
-            r.and_then(|n| {
-                ages[n] += 1;
-                Ok(())
-            })
-        })?;
-    
-    // Part 1
-    (0..80).for_each(|_| {
-        step(&mut ages);
-    });
-    println!("Total fish after 80 days: {}", ages.iter().sum::<u64>());
-
-    // Part 2
-    (80..256).for_each(|_| {
-        step(&mut ages);
-    });
-    println!("Total fish after 256 days: {}", ages.iter().sum::<u64>());
+    let ages =
+        model.parse_ages(
+            &BufReader::new(file)
+                .lines()
+                .next()
+                .ok_or("Read Error")??
+        )?;
+
+    for &days in &model.days {
+        let n = model.population(&ages, days)?;
+        match model.arith {
+            Arith::Exact => println!("Total fish after {} days: {}", days, n),
+            Arith::Modular(m) => println!("Total fish after {} days (mod {}): {}", days, m, n),
+        }
+    }
+
+    if let Some(path) = &model.series {
+        model.write_series(&ages, BufWriter::new(File::create(path)?))?;
+    }
 
     Ok(())
 }
@@ -1,57 +1,329 @@
-use std::{env, error::Error, fs::File, io::{BufReader, BufRead}};
+use std::{env, error::Error, fs::File, io::{BufReader, BufRead}, ops::RangeInclusive, str::FromStr};
 
-use itertools::Itertools;
+use itertools::{Itertools, process_results};
 
-fn l1(y: i32, xs: &[i32]) -> i32 {
-    xs.iter()
-      .map(|&x| (y - x).abs())
-      .sum()
+const OVERFLOW: &str = "overflow, the total cost does not fit in 64 bits";
+
+// Weighted positions along one axis, sorted, with prefix sums so the built-in
+// costs are O(log n) per candidate rather than a full pass. The sums are kept
+// in i128 and the costs narrowed back to i64 at the end.
+struct Crabs {
+    xs: Vec<i64>,
+    ws: Vec<i64>,
+    weights: Vec<i128>,
+    sums: Vec<i128>,
+    sq_sums: Vec<i128>,
+}
+
+impl Crabs {
+    fn new(mut crabs: Vec<(i64, i64)>) -> Result<Crabs, &'static str> {
+        crabs.sort_unstable();
+        let prefix = |f: &dyn Fn(i128, i128) -> Option<i128>| -> Result<Vec<i128>, &'static str> {
+            let mut acc = vec![0i128];
+            for &(x, w) in &crabs {
+                let last = acc[acc.len() - 1];
+                acc.push(f(x as i128, w as i128).and_then(|v| last.checked_add(v)).ok_or(OVERFLOW)?);
+            }
+            Ok(acc)
+        };
+        Ok(Crabs {
+            weights: prefix(&|_, w| Some(w))?,
+            sums: prefix(&|x, w| w.checked_mul(x))?,
+            sq_sums: prefix(&|x, w| w.checked_mul(x)?.checked_mul(x))?,
+            xs: crabs.iter().map(|&(x, _)| x).collect(),
+            ws: crabs.iter().map(|&(_, w)| w).collect(),
+        })
+    }
+
+    fn l1(&self, y: i64) -> Option<i128> {
+        let (n, k) = (self.xs.len(), self.xs.partition_point(|&x| x <= y));
+        let y = y as i128;
+        let (below, total) = (self.sums[k], self.sums[n]);
+        let (w_below, w_above) = (self.weights[k], self.weights[n] - self.weights[k]);
+        let left = w_below.checked_mul(y)?.checked_sub(below)?;
+        let right = (total - below).checked_sub(w_above.checked_mul(y)?)?;
+        left.checked_add(right)
+    }
+
+    fn l2_sq(&self, y: i64) -> Option<i128> {
+        let (n, y) = (self.xs.len(), y as i128);
+        let sq = self.weights[n].checked_mul(y)?.checked_mul(y)?;
+        let cross = self.sums[n].checked_mul(2)?.checked_mul(y)?;
+        sq.checked_sub(cross)?.checked_add(self.sq_sums[n])
+    }
+}
+
+fn narrow(v: Option<i128>) -> Result<i64, &'static str> {
+    v.and_then(|v| i64::try_from(v).ok()).ok_or(OVERFLOW)
+}
+
+// Fuel spent moving a distance `d >= 0`. The minimiser is only exact if the
+// cost is convex and non-decreasing in `d`.
+trait CostFunction {
+    // None when the cost overflows
+    fn cost(&self, d: i64) -> Option<i64>;
+
+    // Whether charging each axis separately is the same as charging the whole
+    // distance, true for L1 distance and squared Euclidean distance only
+    fn separable(&self) -> bool {
+        false
+    }
+
+    fn total(&self, y: i64, crabs: &Crabs) -> Result<i64, &'static str> {
+        crabs.xs
+             .iter()
+             .zip(&crabs.ws)
+             .try_fold(0i64, |acc, (&x, &w)| {
+                 let d = y.checked_sub(x)?.checked_abs()?;
+                 acc.checked_add(w.checked_mul(self.cost(d)?)?)
+             })
+             .ok_or(OVERFLOW)
+    }
+}
+
+struct Linear;
+
+impl CostFunction for Linear {
+    fn cost(&self, d: i64) -> Option<i64> {
+        Some(d)
+    }
+
+    fn separable(&self) -> bool {
+        true
+    }
+
+    fn total(&self, y: i64, crabs: &Crabs) -> Result<i64, &'static str> {
+        narrow(crabs.l1(y))
+    }
+}
+
+struct Triangular;
+
+impl CostFunction for Triangular {
+    fn cost(&self, d: i64) -> Option<i64> {
+        Some(d.checked_mul(d.checked_add(1)?)? / 2)
+    }
+
+    fn total(&self, y: i64, crabs: &Crabs) -> Result<i64, &'static str> {
+        narrow(crabs.l2_sq(y).zip(crabs.l1(y)).and_then(|(sq, l1)| sq.checked_add(l1)).map(|v| v / 2))
+    }
+}
+
+struct Quadratic;
+
+impl CostFunction for Quadratic {
+    fn cost(&self, d: i64) -> Option<i64> {
+        d.checked_mul(d)
+    }
+
+    fn separable(&self) -> bool {
+        true
+    }
+
+    fn total(&self, y: i64, crabs: &Crabs) -> Result<i64, &'static str> {
+        narrow(crabs.l2_sq(y))
+    }
+}
+
+struct Convex<F: Fn(i64) -> Option<i64>>(F);
+
+impl<F: Fn(i64) -> Option<i64>> CostFunction for Convex<F> {
+    fn cost(&self, d: i64) -> Option<i64> {
+        (self.0)(d)
+    }
+}
+
+#[derive(Debug)]
+struct Alignment {
+    positions: RangeInclusive<i64>,
+    cost: i64,
 }
 
-fn not_l2(y: i32, xs: &[i32]) -> i32 {
-    xs.iter()
-      .map(|&x| (y - x).abs() * ((y - x).abs() + 1))
-      .sum::<i32>()/2
+impl Alignment {
+    fn is_tie(&self) -> bool {
+        self.positions.start() != self.positions.end()
+    }
+}
+
+// The total cost is convex in the meeting point, so its forward difference is
+// non-decreasing and the optimal positions can be found by binary search.
+// Any total on the way that overflows is an error rather than a wrapped value.
+fn align<C: CostFunction + ?Sized>(crabs: &Crabs, cost: &C) -> Result<Alignment, &'static str> {
+    let (&lo, &hi) = (crabs.xs.first().ok_or("no crabs")?, crabs.xs.last().ok_or("no crabs")?);
+    let diff = |y: i64| Ok::<_, &'static str>(cost.total(y + 1, crabs)? - cost.total(y, crabs)?);
+
+    let first_where = |pred: &dyn Fn(i64) -> Result<bool, &'static str>| {
+        let (mut l, mut r) = (lo, hi);
+        while l < r {
+            let m = (l as i128 + r as i128).div_euclid(2) as i64;
+            if pred(m)? {
+                r = m;
+            } else {
+                l = m + 1;
+            }
+        }
+        Ok::<_, &'static str>(l)
+    };
+
+    let start = first_where(&|y| Ok(diff(y)? >= 0))?;
+    let end = first_where(&|y| Ok(diff(y)? > 0))?;
+
+    Ok(Alignment {positions: start..=end, cost: cost.total(start, crabs)?})
 }
 
+#[derive(Debug, Clone)]
+struct Crab {
+    pos: Vec<i64>,
+    weight: i64,
+}
+
+// `x[,y[,z...]][*weight]`, weight defaults to 1
+impl FromStr for Crab {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pos, weight) = match s.split_once('*') {
+            Some((p, w)) => (p, w.trim().parse::<i64>()?),
+            None => (s, 1),
+        };
+
+        if weight < 0 {
+            Err(format!("CrabError: negative weight {}", s))?
+        }
+
+        let pos: Vec<i64> =
+            pos.split(',')
+               .map(|x| x.trim().parse::<i64>())
+               .try_collect()?;
+
+        Ok(Crab {pos, weight})
+    }
+}
+
+// How crabs are laid out in the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    // Comma separated 1D crabs
+    List,
+    // One crab per line, in any number of dimensions
+    Points,
+}
+
+// One `Crabs` per axis. In several dimensions a move is charged the cost of
+// its distance along each axis separately, so every axis can be optimised on
+// its own. That is the cost of the L1 distance for linear cost and of the
+// squared Euclidean distance for quadratic cost. Other costs are labelled
+// per axis, triangular cost charges T(|dx|) + T(|dy|) rather than T(|dx| + |dy|).
+struct Swarm {
+    axes: Vec<Crabs>,
+}
+
+impl Swarm {
+    fn new(crabs: &[Crab]) -> Result<Swarm, &'static str> {
+        let dims = crabs.first().ok_or("no crabs")?.pos.len();
+        if crabs.iter().any(|c| c.pos.len() != dims) {
+            return Err("crabs have mixed dimensions");
+        }
+
+        let axes =
+            (0..dims)
+                .map(|k| Crabs::new(crabs.iter().map(|c| (c.pos[k], c.weight)).collect()))
+                .try_collect()?;
+
+        Ok(Swarm {axes})
+    }
+
+    fn parse_swarm<I: Iterator<Item = String>>(iter: I, format: Format) -> Result<Swarm, Box<dyn Error>> {
+        let lines = iter.filter(|s| !s.trim().is_empty()).collect_vec();
+        let crabs: Vec<Crab> =
+            match format {
+                Format::List => lines.iter().flat_map(|s| s.split(',')).map(&str::parse::<Crab>).try_collect()?,
+                Format::Points => lines.iter().map(|s| s.parse::<Crab>()).try_collect()?,
+            };
+
+        Ok(Swarm::new(&crabs)?)
+    }
+
+    fn dims(&self) -> usize {
+        self.axes.len()
+    }
+
+    fn align<C: CostFunction + ?Sized>(&self, cost: &C) -> Result<Vec<Alignment>, &'static str> {
+        self.axes
+            .iter()
+            .map(|crabs| align(crabs, cost))
+            .collect()
+    }
+}
+
+fn report(name: &str, alignments: &[Alignment]) -> Result<(), &'static str> {
+    let cost = alignments.iter().try_fold(0i64, |acc, a| acc.checked_add(a.cost)).ok_or(OVERFLOW)?;
+    let pos = alignments.iter().map(|a| a.positions.start()).join(",");
+    println!("{} cost: {} at position {}", name, cost, pos);
+
+    for (k, alignment) in alignments.iter().enumerate() {
+        if !alignment.is_tie() {
+            continue
+        }
+        if alignments.len() == 1 {
+            println!("  tied at positions {:?}", alignment.positions);
+        } else {
+            println!("  tied along axis {} at positions {:?}", k, alignment.positions);
+        }
+    }
+    Ok(())
+}
+
+const USAGE: &str = "Usage: day7 <input> [linear|triangular|quadratic|pow:K] [points]";
+
 fn main() -> Result<(), Box<dyn Error>> {
     let args = env::args().collect_vec();
-    if args.len() != 2 {
-        panic!("Failed to read input");
+    if args.len() < 2 {
+        panic!("{}", USAGE);
+    }
+
+    let (mut format, mut cost_name) = (Format::List, None);
+    for opt in &args[2..] {
+        match opt.as_str() {
+            "points" => format = Format::Points,
+            _ if cost_name.is_none() => cost_name = Some(opt.as_str()),
+            _ => panic!("{}", USAGE),
+        }
     }
 
     let file = File::open(&args[1])?;
-    let mut crabs =
-        BufReader::new(file)
-            .lines()
-            .exactly_one()??
-            .split(',')
-            .map(&str::parse::<i32>)
-            .collect::<Result<Vec<_>, _>>()?;
-
-    crabs.sort();
-
-    // Part 1
-    let median = crabs[crabs.len()/2];
-
-    println!("L1 for crab median: {}", l1(median, &crabs));
-
-    // Part 2
-    let mean = crabs.iter().sum::<i32>()/(crabs.len() as i32);
-    let range =
-        if median < mean {
-            median..mean + 2
-        } else {
-            mean..median + 1
+    let swarm =
+        process_results(
+            BufReader::new(file).lines(),
+            |iter| Swarm::parse_swarm(iter, format)
+        )??;
+
+    let costs: Vec<(String, Box<dyn CostFunction>)> =
+        match cost_name {
+            None => vec![
+                ("Linear".into(), Box::new(Linear)),
+                ("Triangular".into(), Box::new(Triangular)),
+            ],
+            Some("linear") => vec![("Linear".into(), Box::new(Linear))],
+            Some("triangular") => vec![("Triangular".into(), Box::new(Triangular))],
+            Some("quadratic") => vec![("Quadratic".into(), Box::new(Quadratic))],
+            Some(s) => {
+                let k: u32 =
+                    s.strip_prefix("pow:")
+                     .ok_or("unknown cost function")?
+                     .parse()?;
+                if k == 0 {
+                    return Err("pow:K needs K of at least 1".into());
+                }
+                vec![(format!("Power {}", k), Box::new(Convex(move |d: i64| d.checked_pow(k))))]
+            },
         };
-    let best =
-        range
-            .map(|y| not_l2(y, &crabs))
-            .min()
-            .ok_or("rude")?;
-    
-    println!("Mean: {} Median: {}", mean, median);
-    println!("Best not l2: {}", best);
+
+    for (name, cost) in &costs {
+        let name = if swarm.dims() > 1 && !cost.separable() { format!("{} (per axis)", name) } else { name.clone() };
+        let alignments = swarm.align(cost.as_ref())?;
+        report(&name, &alignments)?;
+    }
 
     Ok(())
 }
@@ -0,0 +1,102 @@
+use std::{error::Error, fmt, str::FromStr};
+
+use utils::XorShift;
+
+// Wires `a` to `g` as bits 0 to 6
+pub fn pattern_mask(s: &str) -> Result<u8, Box<dyn Error>> {
+    s.bytes().try_fold(0, |acc, b| match b {
+        b'a'..=b'g' if acc & (1 << (b - b'a')) == 0 => Ok(acc | 1 << (b - b'a')),
+        _ => Err(format!("PanelError: bad pattern {}", s).into()),
+    })
+}
+
+// One display line as bitmasks, without any allocation per pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Panel {
+    pub patterns: [u8; 10],
+    pub outputs: [u8; 4],
+}
+
+impl FromStr for Panel {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (patterns, outputs) = s.split_once(" | ").ok_or(format!("PanelError: {}", s))?;
+
+        let mut panel = Panel {patterns: [0; 10], outputs: [0; 4]};
+        for (dst, src) in [(&mut panel.patterns[..], patterns), (&mut panel.outputs[..], outputs)] {
+            let mut words = src.split(' ');
+            for d in dst.iter_mut() {
+                *d = pattern_mask(words.next().ok_or(format!("PanelError: {}", s))?)?;
+            }
+            if words.next().is_some() {
+                Err(format!("PanelError: {}", s))?
+            }
+        }
+
+        Ok(panel)
+    }
+}
+
+impl fmt::Display for Panel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |m: u8| -> String {
+            (0..7).filter(|i| m & (1 << i) != 0).map(|i| (b'a' + i) as char).collect()
+        };
+        let patterns: Vec<String> = self.patterns.iter().map(|&m| show(m)).collect();
+        let outputs: Vec<String> = self.outputs.iter().map(|&m| show(m)).collect();
+        write!(f, "{} | {}", patterns.join(" "), outputs.join(" "))
+    }
+}
+
+impl Panel {
+    fn unique(&self, segments: u32) -> Result<u8, &'static str> {
+        let mut found = self.patterns.iter().filter(|m| m.count_ones() == segments);
+        match (found.next(), found.next()) {
+            (Some(&m), None) => Ok(m),
+            (None, _) => Err("unique not found"),
+            _ => Err("unique wrong number"),
+        }
+    }
+
+    // Every digit is told apart by its segment count and how many segments
+    // it shares with 1 and 4, so the rest of the wiring is never needed
+    pub fn decode(&self) -> Result<usize, &'static str> {
+        let (one, four) = (self.unique(2)?, self.unique(4)?);
+
+        self.outputs.iter().try_fold(0, |acc, &o| {
+            let shared = |m: u8| (o & m).count_ones();
+            let digit = match (o.count_ones(), shared(one), shared(four)) {
+                (2, ..) => 1,
+                (3, ..) => 7,
+                (4, ..) => 4,
+                (7, ..) => 8,
+                (5, 2, _) => 3,
+                (5, _, 2) => 2,
+                (5, _, 3) => 5,
+                (6, 1, _) => 6,
+                (6, _, 4) => 9,
+                (6, _, 3) => 0,
+                _ => return Err("could not find number"),
+            };
+            Ok(acc * 10 + digit)
+        })
+    }
+}
+
+// Random rewiring of the standard digits, shuffled, with four random outputs
+pub fn generate(rng: &mut XorShift, digits: &[u8; 10]) -> Panel {
+    let mut wiring = [0, 1, 2, 3, 4, 5, 6];
+    for i in (1..7).rev() {
+        wiring.swap(i, rng.below(i + 1));
+    }
+    let rewire = |m: u8| (0..7).filter(|&i| m & (1 << i) != 0).fold(0, |acc, i| acc | 1 << wiring[i]);
+
+    let mut patterns = digits.map(rewire);
+    for i in (1..10).rev() {
+        patterns.swap(i, rng.below(i + 1));
+    }
+    let outputs = [(); 4].map(|_| rewire(digits[rng.below(10)]));
+
+    Panel {patterns, outputs}
+}
@@ -0,0 +1,155 @@
+use std::{collections::HashMap, error::Error, str::FromStr};
+
+use itertools::Itertools;
+
+use crate::fast::pattern_mask;
+
+// Whatever was logged of one display line, patterns may be missing
+#[derive(Debug, Clone)]
+pub struct Observation {
+    pub patterns: Vec<u8>,
+    pub outputs: Vec<u8>,
+}
+
+impl FromStr for Observation {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (patterns, outputs) = s.split_once('|').ok_or(format!("ObservationError: {}", s))?;
+        let masks = |t: &str| -> Result<Vec<u8>, Box<dyn Error>> {
+            t.split_whitespace().map(pattern_mask).try_collect()
+        };
+
+        Ok(Observation {patterns: masks(patterns)?, outputs: masks(outputs)?})
+    }
+}
+
+// A wiring together with the wires stuck on or off
+#[derive(Debug, Clone, Copy)]
+pub struct Hypothesis {
+    pub wiring: [u8; 7],
+    pub stuck_on: u8,
+    pub stuck_off: u8,
+}
+
+impl Hypothesis {
+    pub fn faults(&self) -> u32 {
+        (self.stuck_on | self.stuck_off).count_ones()
+    }
+}
+
+#[derive(Debug)]
+pub struct Candidate {
+    pub value: u64,
+    pub confidence: f64,
+    pub best: Hypothesis,
+    best_weight: f64,
+}
+
+fn wires(m: u8) -> impl Iterator<Item = usize> {
+    (0..7).filter(move |&w| m & (1 << w) != 0)
+}
+
+// Every way of marking up to `max` wires as stuck on or stuck off
+fn fault_sets(max: u32) -> Vec<(u8, u8)> {
+    let mut sets: Vec<(u8, u8)> = vec![(0, 0)];
+    for w in 0..7 {
+        let bit = 1 << w;
+        let grown =
+            sets.iter()
+                .filter(|&&(on, off)| (on | off).count_ones() < max)
+                .flat_map(|&(on, off)| [(on | bit, off), (on, off | bit)])
+                .collect_vec();
+        sets.extend(grown);
+    }
+    sets
+}
+
+fn permutations() -> Vec<[u8; 7]> {
+    (0..7u8).permutations(7)
+            .map(|p| [p[0], p[1], p[2], p[3], p[4], p[5], p[6]])
+            .collect()
+}
+
+// Can every pattern be a different digit, given the digits each could be
+fn assign(options: &[u16], used: u16) -> bool {
+    match options.split_first() {
+        None => true,
+        Some((&first, rest)) => {
+            (0..10).any(|d| first & !used & (1 << d) != 0 && assign(rest, used | 1 << d))
+        },
+    }
+}
+
+// Ranks output values by the weight of every hypothesis explaining the line,
+// each fault costing a factor `rate / (1 - rate)`. Digits are segment masks,
+// `digits[d]` for digit d.
+pub fn decode(obs: &Observation, digits: &[u8; 10], max_faults: u32, rate: f64) -> Vec<Candidate> {
+    let odds = rate / (1.0 - rate);
+    let faults = fault_sets(max_faults);
+    let mut by_value: HashMap<u64, Candidate> = HashMap::new();
+
+    for wiring in permutations() {
+        // Wires lighting each digit under this wiring
+        let shown = digits.map(|s| {
+            wires(0x7f).filter(|&w| s & (1 << wiring[w]) != 0).fold(0u8, |acc, w| acc | 1 << w)
+        });
+
+        for &(stuck_on, stuck_off) in &faults {
+            let mut lookup = [0u16; 128];
+            for (d, &t) in shown.iter().enumerate() {
+                lookup[((t & !stuck_off) | stuck_on) as usize] |= 1 << d;
+            }
+
+            let options = obs.patterns.iter().map(|&p| lookup[p as usize]).collect_vec();
+            if options.contains(&0) || !assign(&options, 0) {
+                continue
+            }
+            let outputs = obs.outputs.iter().map(|&o| lookup[o as usize]).collect_vec();
+            if outputs.contains(&0) {
+                continue
+            }
+
+            // An output matching several digits splits the weight between them
+            let hypothesis = Hypothesis {wiring, stuck_on, stuck_off};
+            let values =
+                outputs.iter()
+                       .map(|&o| (0..10u64).filter(move |d| o & (1 << d) != 0))
+                       .multi_cartesian_product()
+                       .map(|ds| ds.iter().fold(0, |acc, d| acc * 10 + d))
+                       .collect_vec();
+            let weight = odds.powi(hypothesis.faults() as i32) / values.len() as f64;
+
+            for value in values {
+                let c = by_value.entry(value).or_insert(Candidate {
+                    value,
+                    confidence: 0.0,
+                    best: hypothesis,
+                    best_weight: 0.0,
+                });
+                c.confidence += weight;
+                if weight > c.best_weight {
+                    c.best = hypothesis;
+                    c.best_weight = weight;
+                }
+            }
+        }
+    }
+
+    let total: f64 = by_value.values().map(|c| c.confidence).sum();
+    by_value.into_values()
+            .map(|c| Candidate {confidence: c.confidence / total, ..c})
+            .sorted_by(|a, b| b.confidence.total_cmp(&a.confidence).then(a.value.cmp(&b.value)))
+            .collect()
+}
+
+// Segment lit by each wire in `a..g` order, then the faulty wires
+pub fn describe(h: &Hypothesis) -> String {
+    let name = |i: u8| (b'a' + i) as char;
+    let list = |m: u8| {
+        if m == 0 { "-".to_string() } else { wires(m).map(|w| name(w as u8)).collect() }
+    };
+
+    format!("wiring {} stuck on {} stuck off {}",
+            h.wiring.iter().map(|&s| name(s)).collect::<String>(), list(h.stuck_on), list(h.stuck_off))
+}
@@ -1,6 +1,13 @@
-use std::{error::Error, env, fs::File, str::FromStr, io::{BufReader, BufRead}, collections::{HashMap, HashSet}};
+use std::{error::Error, env, fs::File, time::Instant, str::FromStr, io::{BufReader, BufRead}, collections::{HashMap, HashSet}};
 
-use itertools::{Itertools, process_results};
+use itertools::Itertools;
+use utils::XorShift;
+
+mod fast;
+mod fault;
+mod solver;
+use fast::Panel;
+use solver::{Diagnosis, Font, Wiring};
 
 //   0
 // 1   2
@@ -25,6 +32,7 @@ const NUMMAP: [&str; 10] = [
 
 #[derive(Debug)]
 struct Signal {
+    patterns: Vec<String>,
     digits: HashMap<usize, Vec<HashSet<char>>>,
     outputs: Vec<String>,
 }
@@ -47,7 +55,8 @@ impl FromStr for Signal {
                  .map(|s| HashSet::from_iter(s.chars())) 
                  .into_group_map_by(|s| s.len());
         
-        Ok(Signal {digits: digits_map, outputs: vs.swap_remove(1)})
+        let outputs = vs.swap_remove(1);
+        Ok(Signal {patterns: vs.swap_remove(0), digits: digits_map, outputs})
     }
 }
 
@@ -90,11 +99,11 @@ impl Signal {
         }
 
         // Deduce a from 1 and 7
-        let a = *uniques[2].difference(&uniques[0]).exactly_one().map_err(|_| "not unique")?;
+        let a = *uniques[2].difference(uniques[0]).exactly_one().map_err(|_| "not unique")?;
         deduced.insert(a, 'a');
 
         // Deduce b, d from 4 and 0
-        let d_cands: HashSet<_> = uniques[1].difference(&uniques[0]).copied().collect();
+        let d_cands: HashSet<_> = uniques[1].difference(uniques[0]).copied().collect();
         let d = helper(&self.digits, 0, |digit| d_cands.difference(digit))?;
         let b = *d_cands.iter().find(|&&cand| cand != d).ok_or("could not find")?;
         deduced.insert(b, 'b');
@@ -141,23 +150,188 @@ impl Signal {
 
         Ok(output)
     }
+
+    // Output as shown by every wiring, most solutions agree on it
+    fn decode_with(&self, font: &Font, wirings: &[Wiring]) -> Result<Vec<String>, Box<dyn Error>> {
+        let outputs: Vec<solver::Mask> =
+            self.outputs.iter().map(|s| solver::mask_of(s)).try_collect()?;
+
+        let decoded =
+            wirings.iter()
+                   .map(|w| {
+                       outputs.iter()
+                              .map(|&o| font.lookup(solver::rewire(w, o)).unwrap_or("?"))
+                              .join("")
+                   })
+                   .unique()
+                   .collect();
+        Ok(decoded)
+    }
+
+    fn solve(&self, font: &Font, limit: usize) -> Result<Diagnosis, Box<dyn Error>> {
+        let patterns: Vec<solver::Mask> =
+            self.patterns.iter().map(|s| solver::mask_of(s)).try_collect()?;
+        Ok(solver::diagnose(font, &patterns, limit))
+    }
 }
 
+fn seven_segment() -> Font {
+    let labels = (0..NUMMAP.len()).map(|d| d.to_string()).collect_vec();
+    let table = labels.iter().map(String::as_str).zip(NUMMAP).collect_vec();
+    Font::from_table(&table).expect("valid seven segment font")
+}
+
+// Decodes every line against `font`, summing the outputs when they are numbers
+fn decode_font(signals: &[Signal], font: &Font, limit: usize) -> Result<(), Box<dyn Error>> {
+    let mut sum = Some(0u64);
+    for (i, sig) in signals.iter().enumerate() {
+        let (wirings, status) = match sig.solve(font, limit)? {
+            Diagnosis::Unique(w) => (vec![w], "unique".to_string()),
+            Diagnosis::Ambiguous(ws) => {
+                let count = if ws.len() >= limit { format!("{}+", ws.len()) } else { ws.len().to_string() };
+                (ws, format!("ambiguous, {} wirings", count))
+            },
+            Diagnosis::Unsatisfiable => {
+                println!("line {}: unsatisfiable", i + 1);
+                sum = None;
+                continue
+            },
+            Diagnosis::Unsupported(why) => {
+                println!("line {}: unsupported, {}", i + 1, why);
+                sum = None;
+                continue
+            },
+        };
+
+        let decoded = sig.decode_with(font, &wirings)?;
+        println!("line {}: {} ({})", i + 1, decoded.join(" or "), status);
+        sum = match (sum, decoded.iter().exactly_one().map(|s| s.parse::<u64>())) {
+            (Some(acc), Ok(Ok(n))) => Some(acc + n),
+            _ => None,
+        };
+    }
+
+    match sum {
+        Some(sum) => println!("Output sum: {}", sum),
+        None => println!("Output sum: undetermined"),
+    }
+    Ok(())
+}
+
+// Decodes generated panels with the bitmask path, optionally checking every
+// one against `deduce`
+fn bench(count: usize, check: bool) -> Result<(), Box<dyn Error>> {
+    let digits: Vec<u8> = NUMMAP.iter().map(|s| fast::pattern_mask(s)).try_collect()?;
+    let digits: [u8; 10] = digits.try_into().map_err(|_| "bad digit table")?;
+
+    let mut rng = XorShift(0x2545f4914f6cdd1d);
+    let panels = (0..count).map(|_| fast::generate(&mut rng, &digits)).collect_vec();
+
+    let start = Instant::now();
+    let sum: usize = panels.iter().map(Panel::decode).fold_ok(0, |acc, x| acc + x)?;
+    let elapsed = start.elapsed();
+    println!("Panels: {} Output sum: {}", count, sum);
+    println!("Decoding: {:?} ({:.0} panels/s)", elapsed, count as f64 / elapsed.as_secs_f64());
+
+    if check {
+        for p in &panels {
+            check_panel(p, &p.to_string().parse()?)?;
+        }
+        println!("Checked against deduce: ok");
+    }
+    Ok(())
+}
+
+fn check_panel(panel: &Panel, signal: &Signal) -> Result<usize, Box<dyn Error>> {
+    let (fast, slow) = (panel.decode()?, signal.deduce()?);
+    if fast != slow {
+        Err(format!("mismatch on {}: {} vs {}", panel, fast, slow))?
+    }
+    Ok(fast)
+}
+
+// Decodes lines with missing patterns or faulty wires, listing the likeliest
+// outputs and summing the best of each
+fn decode_faulty(lines: &[String], max_faults: u32, rate: f64) -> Result<(), Box<dyn Error>> {
+    let digits: Vec<u8> = NUMMAP.iter().map(|s| fast::pattern_mask(s)).try_collect()?;
+    let digits: [u8; 10] = digits.try_into().map_err(|_| "bad digit table")?;
+
+    let mut sum = Some(0u64);
+    for (i, line) in lines.iter().enumerate() {
+        let candidates = fault::decode(&line.parse()?, &digits, max_faults, rate);
+        let best = match candidates.first() {
+            Some(best) => best,
+            None => {
+                println!("line {}: no explanation with up to {} faults", i + 1, max_faults);
+                sum = None;
+                continue
+            },
+        };
+
+        let ranking =
+            candidates.iter()
+                      .take(4)
+                      .map(|c| format!("{} ({:.1}%)", c.value, 100.0 * c.confidence))
+                      .join(", ");
+        let more =
+            if candidates.len() > 4 { format!(" +{} more", candidates.len() - 4) } else { String::new() };
+        println!("line {}: {}{}, {}", i + 1, ranking, more, fault::describe(&best.best));
+
+        // A tie for the most likely reading leaves the line undetermined
+        let tied = candidates.get(1).is_some_and(|c| best.confidence - c.confidence < 1e-12);
+        sum = if tied { None } else { sum.map(|acc| acc + best.value) };
+    }
+
+    match sum {
+        Some(sum) => println!("Output sum: {}", sum),
+        None => println!("Output sum: undetermined"),
+    }
+    Ok(())
+}
+
+const USAGE: &str =
+    "Usage: day8 <input> [check] [solve] [font <file>] [limit <n>] [faults <k>] [rate <p>]\n       day8 bench <count> [check]";
+
 fn main() -> Result<(), Box<dyn Error>> {
     let args = env::args().collect_vec();
-    if args.len() != 2 {
-        panic!("Failed to read input");
+    if args.len() < 2 {
+        panic!("{}", USAGE);
+    }
+
+    if args[1] == "bench" {
+        let count = args.get(2).ok_or(USAGE)?.parse()?;
+        return bench(count, args.get(3).map(String::as_str) == Some("check"));
+    }
+
+    let (mut check, mut solve, mut font, mut limit) = (false, false, seven_segment(), 16);
+    let (mut faults, mut rate) = (None, 0.05);
+    let mut opts = args.iter().skip(2);
+    while let Some(opt) = opts.next() {
+        match opt.as_str() {
+            "check" => check = true,
+            "solve" => solve = true,
+            "font" => {
+                font = std::fs::read_to_string(opts.next().ok_or(USAGE)?)?.parse()?;
+                solve = true;
+            },
+            "limit" => limit = opts.next().ok_or(USAGE)?.parse()?,
+            "faults" => faults = Some(opts.next().ok_or(USAGE)?.parse()?),
+            "rate" => {
+                rate = opts.next().ok_or(USAGE)?.parse()?;
+                if !(rate > 0.0 && rate < 1.0) {
+                    return Err(format!("rate must be between 0 and 1, got {}", rate).into());
+                }
+            },
+            _ => panic!("{}", USAGE),
+        }
     }
 
     let file = File::open(&args[1])?;
-    let signals =
-        process_results(
-            BufReader::new(file).lines(),
-            |iter| -> Result<Vec<_>, _> {
-                iter.map(|s| s.parse::<Signal>())
-                    .try_collect()
-            }
-        )??;
+    let lines: Vec<String> = BufReader::new(file).lines().try_collect()?;
+    if let Some(faults) = faults {
+        return decode_faulty(&lines, faults, rate);
+    }
+    let signals: Vec<Signal> = lines.iter().map(|s| s.parse()).try_collect()?;
     
     // Part 1
     // 1, 4, 7, 8 unique segcounts
@@ -175,14 +349,24 @@ fn main() -> Result<(), Box<dyn Error>> {
     
     println!("Number of uniques: {}", num_uniques);
 
+    // Any other display goes through the generic solver
+    if solve {
+        return decode_font(&signals, &font, limit);
+    }
+
     // Part 2
-    let output_sum = 
-        signals
-            .iter()
-            .map(&Signal::deduce)
-            .collect::<Result<Vec<_>, _>>()?
-            .iter()
-            .sum::<usize>();
+    let panels: Vec<Panel> = lines.iter().map(|s| s.parse()).try_collect()?;
+    let output_sum =
+        if check {
+            panels.iter()
+                  .zip(&signals)
+                  .map(|(p, s)| check_panel(p, s))
+                  .fold_ok(0, |acc, x| acc + x)?
+        } else {
+            panels.iter()
+                  .map(Panel::decode)
+                  .fold_ok(0, |acc, x| acc + x)?
+        };
 
     println!("Output sum: {}", output_sum);
 
@@ -0,0 +1,196 @@
+use std::{error::Error, str::FromStr};
+
+use itertools::Itertools;
+
+// Set of wires or segments, `a` is bit 0
+pub type Mask = u32;
+
+pub fn mask_of(s: &str) -> Result<Mask, &'static str> {
+    s.chars().try_fold(0, |acc, c| {
+        if c.is_ascii_lowercase() {
+            Ok(acc | 1 << (c as u32 - 'a' as u32))
+        } else {
+            Err("segments must be letters a-z")
+        }
+    })
+}
+
+fn bits(m: Mask) -> impl Iterator<Item = usize> {
+    (0..Mask::BITS as usize).filter(move |&i| m & (1 << i) != 0)
+}
+
+// Segments lit for each symbol of a display
+#[derive(Debug, Clone)]
+pub struct Font {
+    pub segments: usize,
+    pub symbols: Vec<(String, Mask)>,
+}
+
+impl Font {
+    pub fn from_table(table: &[(&str, &str)]) -> Result<Font, &'static str> {
+        let symbols: Vec<(String, Mask)> =
+            table.iter()
+                 .map(|&(label, segs)| mask_of(segs).map(|m| (label.to_string(), m)))
+                 .try_collect()?;
+
+        if !symbols.iter().map(|&(_, m)| m).all_unique() {
+            return Err("symbols share a segment pattern");
+        }
+
+        let all = symbols.iter().fold(0, |acc, &(_, m)| acc | m);
+        Ok(Font {segments: (Mask::BITS - all.leading_zeros()) as usize, symbols})
+    }
+
+    pub fn all_segments(&self) -> Mask {
+        ((1u64 << self.segments) - 1) as Mask
+    }
+
+    pub fn lookup(&self, segments: Mask) -> Option<&str> {
+        self.symbols
+            .iter()
+            .find(|&&(_, m)| m == segments)
+            .map(|(label, _)| label.as_str())
+    }
+}
+
+// One `<label> <segments>` per line, e.g. `A abcefg`
+impl FromStr for Font {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let table: Vec<(&str, &str)> =
+            s.lines()
+             .filter(|l| !l.trim().is_empty())
+             .map(|l| l.split_whitespace().collect_tuple().ok_or(format!("FontError: {}", l)))
+             .try_collect()?;
+
+        Ok(Font::from_table(&table)?)
+    }
+}
+
+// wiring[w] is the segment lit by wire w
+pub type Wiring = Vec<usize>;
+
+pub fn rewire(wiring: &Wiring, wires: Mask) -> Mask {
+    bits(wires).fold(0, |acc, w| acc | 1 << wiring[w])
+}
+
+#[derive(Debug)]
+pub enum Diagnosis {
+    Unique(Wiring),
+    Ambiguous(Vec<Wiring>),
+    Unsatisfiable,
+    // More wires than the font has segments, or a wrong number of patterns
+    Unsupported(&'static str),
+}
+
+struct Search<'a> {
+    font: &'a Font,
+    patterns: Vec<Mask>,
+    limit: usize,
+    solutions: Vec<Wiring>,
+}
+
+impl<'a> Search<'a> {
+    // Can `pattern` still be shown as `symbol` given the wire domains
+    fn fits(&self, domains: &[Mask], pattern: Mask, symbol: Mask) -> bool {
+        pattern.count_ones() == symbol.count_ones() &&
+        domains.iter().enumerate().all(|(w, &d)| {
+            if pattern & (1 << w) != 0 {
+                d & symbol != 0
+            } else {
+                d & !symbol != 0
+            }
+        })
+    }
+
+    fn search(&mut self, domains: Vec<Mask>, assigned: Vec<bool>, used: Vec<bool>) {
+        if self.solutions.len() >= self.limit {
+            return;
+        }
+
+        // Most constrained pattern first
+        let next =
+            (0..self.patterns.len())
+                .filter(|&p| !assigned[p])
+                .map(|p| {
+                    let options =
+                        self.font.symbols
+                            .iter()
+                            .enumerate()
+                            .filter(|&(s, &(_, m))| !used[s] && self.fits(&domains, self.patterns[p], m))
+                            .map(|(s, _)| s)
+                            .collect_vec();
+                    (p, options)
+                })
+                .min_by_key(|(_, options)| options.len());
+
+        let (p, options) = match next {
+            Some(next) => next,
+            None => return self.matchings(&domains, 0, 0, &mut vec![0; domains.len()]),
+        };
+
+        let pattern = self.patterns[p];
+        for s in options {
+            let symbol = self.font.symbols[s].1;
+            let narrowed =
+                domains.iter()
+                       .enumerate()
+                       .map(|(w, &d)| if pattern & (1 << w) != 0 { d & symbol } else { d & !symbol })
+                       .collect_vec();
+            if narrowed.contains(&0) {
+                continue
+            }
+
+            let (mut now_assigned, mut now_used) = (assigned.clone(), used.clone());
+            now_assigned[p] = true;
+            now_used[s] = true;
+            self.search(narrowed, now_assigned, now_used);
+        }
+    }
+
+    // Every one-to-one wiring allowed by the domains
+    fn matchings(&mut self, domains: &[Mask], w: usize, taken: Mask, wiring: &mut Wiring) {
+        if self.solutions.len() >= self.limit {
+            return;
+        }
+        if w == domains.len() {
+            self.solutions.push(wiring.clone());
+            return;
+        }
+
+        for seg in bits(domains[w] & !taken) {
+            wiring[w] = seg;
+            self.matchings(domains, w + 1, taken | 1 << seg, wiring);
+        }
+    }
+}
+
+// Up to `limit` wirings that show every observed pattern as a distinct symbol
+pub fn solve(font: &Font, patterns: &[Mask], limit: usize) -> Result<Vec<Wiring>, &'static str> {
+    let patterns = patterns.iter().copied().unique().collect_vec();
+    let wires = font.all_segments();
+    if patterns.len() > font.symbols.len() {
+        return Err("more patterns than symbols");
+    }
+    if patterns.iter().any(|&p| p & !wires != 0) {
+        return Err("wires beyond the font's segments");
+    }
+
+    let mut search = Search {font, limit, solutions: vec![], patterns};
+    let assigned = vec![false; search.patterns.len()];
+    search.search(vec![wires; font.segments], assigned, vec![false; font.symbols.len()]);
+    Ok(search.solutions)
+}
+
+pub fn diagnose(font: &Font, patterns: &[Mask], limit: usize) -> Diagnosis {
+    let mut solutions = match solve(font, patterns, limit.max(2)) {
+        Ok(solutions) => solutions,
+        Err(e) => return Diagnosis::Unsupported(e),
+    };
+    match solutions.len() {
+        0 => Diagnosis::Unsatisfiable,
+        1 => Diagnosis::Unique(solutions.remove(0)),
+        _ => Diagnosis::Ambiguous(solutions),
+    }
+}
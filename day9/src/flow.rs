@@ -0,0 +1,375 @@
+use std::{collections::VecDeque, error::Error, io::Write};
+
+use utils::{Coord, Grid};
+
+use crate::heightmap::Heightmap;
+
+// Where each cell drains, upstream cells first in `order`. Sinks drain
+// nowhere.
+#[derive(Debug)]
+pub struct Flow {
+    pub down: Grid<Option<Coord>>,
+    pub order: Vec<Coord>,
+}
+
+// Cells grouped by the sink they drain to, level pits of several sinks
+// count as one
+#[derive(Debug)]
+pub struct Watersheds {
+    pub label: Grid<usize>,
+    pub sinks: Vec<Coord>,
+}
+
+#[derive(Debug)]
+pub enum RainEvent {
+    // The lake at `sink` reached `level` and now overflows, off the map when
+    // `into` is none
+    Full {time: f64, sink: Coord, level: u32, into: Option<Coord>},
+    // Lakes spilling into each other became one
+    Merge {time: f64, sinks: Vec<Coord>},
+}
+
+#[derive(Debug)]
+pub struct Lake {
+    pub sink: Coord,
+    pub cells: Vec<Coord>,
+    pub volume: f64,
+    pub capacity: f64,
+    // Height the lake spills at, and where it goes
+    pub level: u32,
+    target: Option<usize>,
+    pub full: bool,
+    reported: bool,
+}
+
+fn row_order(c: Coord) -> (usize, usize) {
+    (c.y, c.x)
+}
+
+impl Heightmap {
+    // A cell drains to its lowest neighbour if that is lower than itself,
+    // the first in row order on a tie. A cell on a level area with no lower
+    // neighbour drains towards the nearest cell of that area that does, again
+    // favouring row order, and is a sink if there is none.
+    pub fn flow(&self) -> Flow {
+        let (w, h) = self.heights.size();
+        let mut down: Grid<Option<Coord>> = Grid::filled(w, h, None);
+        let mut working = VecDeque::new();
+
+        for y in 0..h {
+            for x in 0..w {
+                let c = Coord {x, y};
+                let lowest =
+                    self.neighbors(c)
+                        .min_by_key(|&n| (self.height(n), row_order(n)))
+                        .filter(|&n| self.height(n) < self.height(c));
+                if lowest.is_some() {
+                    *down.index_mut(x, y) = lowest;
+                    working.push_back(c);
+                }
+            }
+        }
+
+        // Breadth first over level ground from the cells that can drain
+        while let Some(cur) = working.pop_front() {
+            let mut level =
+                self.neighbors(cur)
+                    .filter(|&n| self.height(n) == self.height(cur) && down.index(n.x, n.y).is_none())
+                    .collect::<Vec<_>>();
+            level.sort_by_key(|&n| row_order(n));
+            for n in level {
+                *down.index_mut(n.x, n.y) = Some(cur);
+                working.push_back(n);
+            }
+        }
+
+        // Upstream first, by counting the cells draining into each one
+        let mut inflow = Grid::filled(w, h, 0usize);
+        for (d, _) in down.iter_coords().flatten() {
+            if let Some(d) = d {
+                *inflow.index_mut(d.x, d.y) += 1;
+            }
+        }
+        let mut ready: VecDeque<Coord> =
+            down.iter_coords()
+                .flatten()
+                .filter(|&(_, (x, y))| *inflow.index(x, y) == 0)
+                .map(|(_, (x, y))| Coord {x, y})
+                .collect();
+        let mut order = vec![];
+        while let Some(c) = ready.pop_front() {
+            order.push(c);
+            if let Some(d) = *down.index(c.x, c.y) {
+                let n = inflow.index_mut(d.x, d.y);
+                *n -= 1;
+                if *n == 0 {
+                    ready.push_back(d);
+                }
+            }
+        }
+
+        Flow {down, order}
+    }
+}
+
+impl Flow {
+    // Number of cells whose rain passes through each cell, itself included
+    pub fn accumulation(&self) -> Grid<usize> {
+        let (w, h) = self.down.size();
+        let mut acc = Grid::filled(w, h, 1);
+        for &c in &self.order {
+            if let Some(d) = *self.down.index(c.x, c.y) {
+                let here = *acc.index(c.x, c.y);
+                *acc.index_mut(d.x, d.y) += here;
+            }
+        }
+        acc
+    }
+
+    pub fn watersheds(&self, heightmap: &Heightmap) -> Watersheds {
+        let (w, h) = self.down.size();
+        let mut label: Grid<Option<usize>> = Grid::filled(w, h, None);
+        let mut sinks = vec![];
+
+        for &c in &self.order {
+            if self.down.index(c.x, c.y).is_some() || label.index(c.x, c.y).is_some() {
+                continue
+            }
+
+            // Level neighbouring sinks form one pit
+            let id = sinks.len();
+            let mut pit = vec![c];
+            *label.index_mut(c.x, c.y) = Some(id);
+            let mut working = VecDeque::from([c]);
+            while let Some(cur) = working.pop_front() {
+                for n in heightmap.neighbors(cur) {
+                    if self.down.index(n.x, n.y).is_none() && label.index(n.x, n.y).is_none() &&
+                       heightmap.height(n) == heightmap.height(cur)
+                    {
+                        *label.index_mut(n.x, n.y) = Some(id);
+                        pit.push(n);
+                        working.push_back(n);
+                    }
+                }
+            }
+            sinks.push(*pit.iter().min_by_key(|&&p| row_order(p)).expect("pit has a cell"));
+        }
+
+        // Downstream first, so every cell takes the label it drains to
+        for &c in self.order.iter().rev() {
+            if let Some(d) = *self.down.index(c.x, c.y) {
+                let l = *label.index(d.x, d.y);
+                *label.index_mut(c.x, c.y) = l;
+            }
+        }
+
+        let label = label.iter_coords().map(|row| row.map(|(l, _)| l.expect("every cell drains"))).collect();
+        Watersheds {label, sinks}
+    }
+}
+
+impl Watersheds {
+    pub fn get(&self, c: Coord) -> usize {
+        *self.label.index(c.x, c.y)
+    }
+
+    // Cells next to a cell of another watershed
+    pub fn boundaries(&self, heightmap: &Heightmap) -> Vec<Coord> {
+        let (w, h) = self.label.size();
+        (0..h).flat_map(|y| (0..w).map(move |x| Coord {x, y}))
+              .filter(|&c| heightmap.neighbors(c).any(|n| self.get(n) != self.get(c)))
+              .collect()
+    }
+}
+
+struct Lakes<'a> {
+    heightmap: &'a Heightmap,
+    // Union find over watersheds, a lake is kept at its root
+    parent: Vec<usize>,
+    lakes: Vec<Option<Lake>>,
+}
+
+impl<'a> Lakes<'a> {
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn lake(&self, i: usize) -> &Lake {
+        self.lakes[i].as_ref().expect("lake is a root")
+    }
+
+    // Lowest pass out of the lake, the map edge being open, then the water
+    // it holds below that
+    fn spill(&mut self, i: usize, ws: &Watersheds) {
+        let hm = self.heightmap;
+        let (w, h) = hm.heights.size();
+        let cells = self.lake(i).cells.clone();
+
+        let mut best: Option<(u32, (usize, usize), Option<usize>)> = None;
+        for &c in &cells {
+            let on_edge = c.x == 0 || c.y == 0 || c.x == w - 1 || c.y == h - 1;
+            let mut passes = vec![];
+            if on_edge {
+                passes.push((hm.height(c), row_order(c), None));
+            }
+            for n in hm.neighbors(c) {
+                let other = self.find(ws.get(n));
+                if other != i {
+                    passes.push((hm.height(c).max(hm.height(n)), row_order(c), Some(other)));
+                }
+            }
+            for p in passes {
+                if best.is_none_or(|b| (p.0, p.1) < (b.0, b.1)) {
+                    best = Some(p);
+                }
+            }
+        }
+
+        let (level, _, target) = best.unwrap_or((u32::MAX, (0, 0), None));
+        let capacity = cells.iter().map(|&c| level.saturating_sub(hm.height(c)) as f64).sum();
+        let lake = self.lakes[i].as_mut().expect("lake is a root");
+        lake.level = level;
+        lake.capacity = capacity;
+        lake.target = target;
+        lake.full = lake.volume >= lake.capacity;
+    }
+
+    // Where water arriving at lake i ends up, none when it leaves the map
+    fn destination(&mut self, i: usize) -> Option<usize> {
+        let mut cur = i;
+        for _ in 0..self.parent.len() {
+            let lake = self.lake(cur);
+            if !lake.full {
+                return Some(cur);
+            }
+            cur = self.find(lake.target?);
+        }
+        None
+    }
+}
+
+// Rain falls at `rate` per cell per unit of time, runs down to the lake of
+// its watershed, and once a lake is full overflows across its lowest pass.
+// Lakes spilling into each other merge. Runs until `until` or until every
+// lake is full and water only leaves the map.
+pub fn rainfall(heightmap: &Heightmap, ws: &Watersheds, rate: f64, until: f64) -> (Vec<RainEvent>, Vec<Lake>) {
+    let n = ws.sinks.len();
+    let mut cells = vec![vec![]; n];
+    for row in ws.label.iter_coords() {
+        for (&l, (x, y)) in row {
+            cells[l].push(Coord {x, y});
+        }
+    }
+
+    let mut lakes = Lakes {
+        heightmap,
+        parent: (0..n).collect(),
+        lakes: cells.into_iter()
+                    .zip(&ws.sinks)
+                    .map(|(cells, &sink)| Some(Lake {
+                        sink, cells, volume: 0.0, capacity: 0.0, level: 0, target: None, full: false, reported: false,
+                    }))
+                    .collect(),
+    };
+    (0..n).for_each(|i| lakes.spill(i, ws));
+
+    let mut events = vec![];
+    let mut time = 0.0;
+    loop {
+        // Report newly full lakes, merging any that now spill in a circle
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in 0..n {
+                let lake = match lakes.lakes[i].as_mut() {
+                    Some(lake) if lake.full && !lake.reported => lake,
+                    _ => continue,
+                };
+                lake.reported = true;
+                let (sink, level, target) = (lake.sink, lake.level, lake.target);
+                let into = target.map(|t| lakes.find(t));
+                events.push(RainEvent::Full {time, sink, level, into: into.map(|t| lakes.lake(t).sink)});
+
+                // Follow the full lakes downstream, back to i means a circle
+                let mut circle = vec![i];
+                let mut cur = into;
+                while let Some(c) = cur {
+                    if c == i || !lakes.lake(c).full || circle.contains(&c) {
+                        break
+                    }
+                    circle.push(c);
+                    cur = lakes.lake(c).target.map(|t| lakes.find(t));
+                }
+                if cur != Some(i) {
+                    continue
+                }
+
+                let mut merged = lakes.lakes[i].take().expect("lake is a root");
+                let mut sinks = vec![merged.sink];
+                for &c in &circle[1..] {
+                    let other = lakes.lakes[c].take().expect("lake is a root");
+                    lakes.parent[c] = i;
+                    sinks.push(other.sink);
+                    merged.cells.extend(other.cells);
+                    merged.volume += other.volume;
+                }
+                merged.reported = false;
+                lakes.lakes[i] = Some(merged);
+                lakes.spill(i, ws);
+                events.push(RainEvent::Merge {time, sinks});
+                changed = true;
+            }
+        }
+
+        // Inflow of every filling lake
+        let mut inflow = vec![0.0; n];
+        for i in 0..n {
+            if lakes.lakes[i].is_none() {
+                continue
+            }
+            let rain = rate * lakes.lake(i).cells.len() as f64;
+            if let Some(d) = lakes.destination(i) {
+                inflow[d] += rain;
+            }
+        }
+
+        let step =
+            (0..n).filter(|&i| inflow[i] > 0.0)
+                  .map(|i| (lakes.lake(i).capacity - lakes.lake(i).volume) / inflow[i])
+                  .fold(f64::INFINITY, f64::min)
+                  .min(until - time);
+        if !step.is_finite() {
+            break
+        }
+
+        time += step;
+        for (i, &rain) in inflow.iter().enumerate() {
+            if rain > 0.0 {
+                let lake = lakes.lakes[i].as_mut().expect("lake is a root");
+                lake.volume = (lake.volume + rain * step).min(lake.capacity);
+                if lake.capacity - lake.volume < 1e-9 {
+                    lake.volume = lake.capacity;
+                    lake.full = true;
+                }
+            }
+        }
+        if time >= until {
+            break
+        }
+    }
+
+    (events, lakes.lakes.into_iter().flatten().collect())
+}
+
+// One row of comma separated values per row of the grid
+pub fn write_grid<W: Write>(grid: &Grid<usize>, mut w: W) -> Result<(), Box<dyn Error>> {
+    for row in grid.iter_coords() {
+        let cells: Vec<String> = row.map(|(v, _)| v.to_string()).collect();
+        writeln!(w, "{}", cells.join(","))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,178 @@
+use std::{collections::{BTreeMap, BTreeSet, VecDeque}, error::Error, io::Write, str::FromStr};
+
+use utils::{Coord, Grid};
+
+pub type BasinId = usize;
+
+// Height of the walls between basins
+pub const WALL: u32 = 9;
+
+// How to treat a cell no higher than its neighbours but level with some
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlateauPolicy {
+    // Only cells strictly lower than every neighbour, level cells never count
+    Strict,
+    // A level area with only higher cells around it is one low point, its
+    // first cell in row order
+    Plateau,
+    // Every cell no higher than any neighbour is a low point
+    Each,
+}
+
+impl FromStr for PlateauPolicy {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(PlateauPolicy::Strict),
+            "plateau" => Ok(PlateauPolicy::Plateau),
+            "each" => Ok(PlateauPolicy::Each),
+            _ => Err(format!("PolicyError: {}", s).into()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Basin {
+    pub low: Coord,
+    pub height: u32,
+    pub size: usize,
+    // Number of cells at each height, from 0 up to just below the walls
+    pub profile: Vec<usize>,
+    // Wall cells bordering the basin, in row order
+    pub boundary: Vec<Coord>,
+}
+
+#[derive(Debug)]
+pub struct Heightmap {
+    pub heights: Grid<u32>,
+}
+
+impl Heightmap {
+    pub fn height(&self, c: Coord) -> u32 {
+        *self.heights.index(c.x, c.y)
+    }
+
+    // Orthogonal neighbours inside the map
+    pub fn neighbors(&self, c: Coord) -> impl Iterator<Item = Coord> {
+        let (w, h) = self.heights.size();
+        [(0, 1), (2, 1), (1, 0), (1, 2)]
+            .into_iter()
+            .map(move |(dx, dy)| ((c.x + dx).wrapping_sub(1), (c.y + dy).wrapping_sub(1)))
+            .filter(move |&(x, y)| x < w && y < h)
+            .map(|(x, y)| Coord {x, y})
+    }
+
+    fn coords(&self) -> impl Iterator<Item = Coord> {
+        let (w, h) = self.heights.size();
+        (0..h).flat_map(move |y| (0..w).map(move |x| Coord {x, y}))
+    }
+
+    // Wall cells are never low points, whatever the policy
+    pub fn low_points(&self, policy: PlateauPolicy) -> Vec<Coord> {
+        let (w, h) = self.heights.size();
+        let mut seen = Grid::filled(w, h, false);
+        let mut lows = vec![];
+
+        for c in self.coords() {
+            let height = self.height(c);
+            if height >= WALL {
+                continue
+            }
+            let lowest = self.neighbors(c).map(|n| self.height(n)).min().unwrap_or(u32::MAX);
+            match policy {
+                PlateauPolicy::Strict if height < lowest => lows.push(c),
+                PlateauPolicy::Each if height <= lowest => lows.push(c),
+                PlateauPolicy::Plateau if height <= lowest && !seen.index(c.x, c.y) => {
+                    // Walk the level area, it is a low point if nothing around is lower
+                    let mut is_low = true;
+                    let mut working = VecDeque::from([c]);
+                    *seen.index_mut(c.x, c.y) = true;
+                    while let Some(cur) = working.pop_front() {
+                        for n in self.neighbors(cur) {
+                            let nh = self.height(n);
+                            if nh < height {
+                                is_low = false;
+                            } else if nh == height && !seen.index(n.x, n.y) {
+                                *seen.index_mut(n.x, n.y) = true;
+                                working.push_back(n);
+                            }
+                        }
+                    }
+                    if is_low {
+                        lows.push(c);
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        lows
+    }
+
+    // Floods out from each low point up to the walls. Low points already
+    // inside an earlier basin add nothing, so basins are numbered in the row
+    // order of their first low point.
+    pub fn basins(&self, policy: PlateauPolicy) -> (Vec<Basin>, Grid<Option<BasinId>>) {
+        let (w, h) = self.heights.size();
+        let mut map: Grid<Option<BasinId>> = Grid::filled(w, h, None);
+        let mut basins = vec![];
+
+        for low in self.low_points(policy) {
+            if map.index(low.x, low.y).is_some() {
+                continue
+            }
+
+            let id = basins.len();
+            let mut basin = Basin {
+                low,
+                height: self.height(low),
+                size: 0,
+                profile: vec![0; WALL as usize],
+                boundary: vec![],
+            };
+            let mut boundary = BTreeSet::new();
+
+            let mut working = VecDeque::from([low]);
+            *map.index_mut(low.x, low.y) = Some(id);
+            while let Some(cur) = working.pop_front() {
+                basin.size += 1;
+                basin.profile[self.height(cur) as usize] += 1;
+
+                for n in self.neighbors(cur) {
+                    if self.height(n) >= WALL {
+                        boundary.insert((n.y, n.x));
+                    } else if map.index(n.x, n.y).is_none() {
+                        *map.index_mut(n.x, n.y) = Some(id);
+                        working.push_back(n);
+                    }
+                }
+            }
+
+            basin.boundary = boundary.into_iter().map(|(y, x)| Coord {x, y}).collect();
+            basins.push(basin);
+        }
+
+        (basins, map)
+    }
+}
+
+// Number of basins of each size
+pub fn size_histogram(basins: &[Basin]) -> BTreeMap<usize, usize> {
+    let mut histogram = BTreeMap::new();
+    for b in basins {
+        *histogram.entry(b.size).or_insert(0) += 1;
+    }
+    histogram
+}
+
+// Basin id of every cell, comma separated, `-` outside any basin
+pub fn write_basin_map<W: Write>(map: &Grid<Option<BasinId>>, mut w: W) -> Result<(), Box<dyn Error>> {
+    for row in map.iter_coords() {
+        let cells: Vec<String> =
+            row.map(|(id, _)| id.map_or("-".to_string(), |id| id.to_string()))
+               .collect();
+        writeln!(w, "{}", cells.join(","))?;
+    }
+    Ok(())
+}
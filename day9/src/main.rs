@@ -1,97 +1,132 @@
-use std::{error::Error, env, fs::File, io::{BufReader, BufRead}, collections::{VecDeque}};
+use std::{cmp::Reverse, error::Error, env, fs::File, io::{BufReader, BufRead}};
 
 use itertools::{Itertools, process_results};
+use utils::Grid;
+
+mod flow;
+mod heightmap;
+use flow::RainEvent;
+use heightmap::{Heightmap, PlateauPolicy};
+
+const USAGE: &str = "Usage: day9 <input> [policy <strict|plateau|each>] [basins] [histogram] [map <output>]\n       \
+                     [flow] [accum <output>] [watersheds] [rain <rate>] [until <time>]";
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = env::args().collect_vec();
-    if args.len() != 2 {
-        panic!("Failed to read input");
+    if args.len() < 2 {
+        panic!("{}", USAGE);
+    }
+
+    let (mut policy, mut list, mut histogram, mut map_file) = (PlateauPolicy::Strict, false, false, None);
+    let (mut show_flow, mut accum_file, mut show_watersheds) = (false, None, false);
+    let (mut rain, mut until) = (None, f64::INFINITY);
+    let mut opts = args.iter().skip(2);
+    while let Some(opt) = opts.next() {
+        match opt.as_str() {
+            "policy" => policy = opts.next().ok_or(USAGE)?.parse()?,
+            "basins" => list = true,
+            "histogram" => histogram = true,
+            "map" => map_file = Some(opts.next().ok_or(USAGE)?),
+            "flow" => show_flow = true,
+            "accum" => accum_file = Some(opts.next().ok_or(USAGE)?),
+            "watersheds" => show_watersheds = true,
+            "rain" => rain = Some(opts.next().ok_or(USAGE)?.parse::<f64>()?),
+            "until" => until = opts.next().ok_or(USAGE)?.parse()?,
+            _ => panic!("{}", USAGE),
+        }
     }
 
     let file = File::open(&args[1])?;
-    let heights: Vec<Vec<u32>> =
+    let heights: Grid<u32> =
         process_results(
             BufReader::new(file).lines(),
-            |iter| {
-                iter.map(|s| -> Result<Vec<_>, _> {
-                    s.chars()
-                     .map(|c| c.to_digit(10).ok_or("not a digit"))
-                     .try_collect()
-                })
-                .try_collect()
-            }
+            |iter| Grid::parse_grid(iter.filter(|s| !s.is_empty()), "")
         )??;
-    
-    // Part 1
-    let ymax = heights.len();
-    let xmax = heights[0].len();
-    let mut minima: Vec<(usize, usize, u32)> = vec![];
-
-    let get_neighbors = |x, y| {
-        let mut neighbors: Vec<(usize, usize)> = vec![];
-        if x > 0 {
-            neighbors.push((x - 1, y));
-        }
-        if x < xmax - 1 {
-            neighbors.push((x + 1, y));
-        }
-        if y > 0 {
-            neighbors.push((x, y - 1));
-        }
-        if y < ymax - 1 {
-            neighbors.push((x, y + 1));
-        }
-        neighbors
-    };
-
-    for (y, hs) in heights.iter().enumerate() {
-        for (x, &h) in hs.iter().enumerate() {
-            let m =
-                get_neighbors(x, y)
-                    .iter()
-                    .map(|&(x, y)| heights[y][x])
-                    .min()
-                    .ok_or("no min")?;
-            if h < m {
-                minima.push((x, y, h));
-            }
-        }
-    }
+    let heightmap = Heightmap {heights};
 
-    let total_risk: u32 = minima.iter().map(|&(_, _, h)| h + 1).sum();
+    // Part 1
+    let total_risk: u32 =
+        heightmap.low_points(policy)
+                 .iter()
+                 .map(|&c| heightmap.height(c) + 1)
+                 .sum();
 
     println!("Total Risk: {}", total_risk);
 
     // Part 2
-    let mut visited: Vec<Vec<bool>> = vec![vec![false; xmax]; ymax];
-    let mut flood = |x: usize, y: usize| {
-        let mut count = 0;
-        let mut working = VecDeque::new();
-        working.push_back((x, y));
-
-        while let Some((nx, ny)) = working.pop_front() {
-            if visited[ny][nx] || heights[ny][nx] == 9 {
-                continue
-            }
+    let (basins, map) = heightmap.basins(policy);
 
-            visited[ny][nx] = true;
-            count += 1;
+    if list {
+        for (id, b) in basins.iter().enumerate() {
+            println!("basin {}: low {},{} height {} size {} profile [{}] boundary {} cells",
+                     id, b.low.x, b.low.y, b.height, b.size, b.profile.iter().join(" "), b.boundary.len());
+        }
+    }
 
-            get_neighbors(nx, ny).iter().for_each(|&t| working.push_back(t));
+    if histogram {
+        for (size, count) in heightmap::size_histogram(&basins) {
+            println!("{:>4} | {:<4} {}", size, count, "#".repeat(count));
         }
+    }
 
-        count
-    };
+    if let Some(path) = map_file {
+        heightmap::write_basin_map(&map, File::create(path)?)?;
+    }
 
-    let best: u32 =
-        minima.iter()
-              .map(|&(x, y, _)| flood(x, y))
+    let best: usize =
+        basins.iter()
+              .map(|b| b.size)
               .sorted()
               .rev()
               .take(3)
               .product();
-    
+
     println!("Basin product: {}", best);
 
+    if !show_flow && accum_file.is_none() && !show_watersheds && rain.is_none() {
+        return Ok(());
+    }
+
+    let flow = heightmap.flow();
+    let accumulation = flow.accumulation();
+    if show_flow {
+        let (&most, (x, y)) =
+            accumulation.iter_coords()
+                        .flatten()
+                        .max_by_key(|&(&a, (x, y))| (a, Reverse((y, x))))
+                        .ok_or("empty heightmap")?;
+        println!("Max accumulation: {} at {},{}", most, x, y);
+    }
+    if let Some(path) = accum_file {
+        flow::write_grid(&accumulation, File::create(path)?)?;
+    }
+
+    let watersheds = flow.watersheds(&heightmap);
+    if show_watersheds {
+        println!("Watersheds: {} Boundary cells: {}",
+                 watersheds.sinks.len(), watersheds.boundaries(&heightmap).len());
+    }
+
+    if let Some(rate) = rain {
+        let (events, lakes) = flow::rainfall(&heightmap, &watersheds, rate, until);
+        for e in &events {
+            match e {
+                RainEvent::Full {time, sink, level, into} => {
+                    let into = into.map_or("off the map".to_string(), |c| format!("into {},{}", c.x, c.y));
+                    println!("t={:.3}: lake {},{} full at level {}, spills {}", time, sink.x, sink.y, level, into);
+                },
+                RainEvent::Merge {time, sinks} => {
+                    let sinks = sinks.iter().map(|c| format!("{},{}", c.x, c.y)).join(" ");
+                    println!("t={:.3}: lakes {} merge", time, sinks);
+                },
+            }
+        }
+        for lake in lakes.iter().filter(|l| l.capacity > 0.0) {
+            println!("lake {},{}: {} cells, {:.1}/{:.1} held, spills at {}{}",
+                     lake.sink.x, lake.sink.y, lake.cells.len(), lake.volume, lake.capacity, lake.level,
+                     if lake.full { " (full)" } else { "" });
+        }
+    }
+
     Ok(())
 }
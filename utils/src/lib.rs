@@ -26,9 +26,10 @@ impl FromStr for Coord {
     }
 }
 
+// Row-major grid stored in a single flat buffer
 #[derive(Debug, Clone)]
 pub struct Grid<T> {
-    data: Vec<Vec<T>>,
+    data: Vec<T>,
     width: usize,
     height: usize,
 }
@@ -41,8 +42,9 @@ impl<T> Grid<T> {
         } else {
             data[0].len()
         };
+        assert!(data.iter().all(|r| r.len() == width), "Grid rows must have equal length");
 
-        Grid {data, height, width}
+        Grid {data: data.into_iter().flatten().collect(), height, width}
     }
 
     pub fn size(&self) -> (usize, usize) {
@@ -50,19 +52,27 @@ impl<T> Grid<T> {
     }
 
     pub fn get(&self, x: usize, y: usize) -> Option<&T> {
-        self.data.get(y).and_then(|r| r.get(x))
+        if x < self.width && y < self.height {
+            self.data.get(y * self.width + x)
+        } else {
+            None
+        }
     }
 
     pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut T> {
-        self.data.get_mut(y).and_then(|r| r.get_mut (x))
+        if x < self.width && y < self.height {
+            self.data.get_mut(y * self.width + x)
+        } else {
+            None
+        }
     }
 
     pub fn index(&self, x: usize, y: usize) -> &T {
-        &self.data[y][x]
+        self.get(x, y).expect("Grid index out of bounds")
     }
 
     pub fn index_mut(&mut self, x: usize, y: usize) -> &mut T {
-        &mut self.data[y][x]
+        self.get_mut(x, y).expect("Grid index out of bounds")
     }
 
     pub fn iter_neighbors(&self, x: usize, y: usize) -> impl Iterator<Item = (usize, usize)> {
@@ -86,7 +96,7 @@ impl<T> Grid<T> {
             (0..self.height)
                 .map(|y| (0..self.width).map(move |x| (x, y)));
         self.data
-            .iter()
+            .chunks(self.width.max(1))
             .zip(coords)
             .map(|(v, cs)| v.iter().zip(cs))
     }
@@ -98,15 +108,21 @@ impl<T> Grid<T> {
             (0..self.height)
                 .map(|y| (0..self.width).map(move |x| (x, y)));
         self.data
-            .iter_mut()
+            .chunks_mut(self.width.max(1))
             .zip(coords)
             .map(|(v, cs)| v.iter_mut().zip(cs))
     }
 }
 
+impl<T: Clone> Grid<T> {
+    pub fn filled(width: usize, height: usize, value: T) -> Grid<T> {
+        Grid {data: vec![value; width * height], width, height}
+    }
+}
+
 impl<T: fmt::Display> Grid<T> {
     pub fn show(&self) {
-        for row in &self.data {
+        for row in self.data.chunks(self.width.max(1)) {
             for t in row {
                 print!("{}", t);
             }
@@ -148,6 +164,23 @@ impl<T: FromStr> Grid<T> {
                 })
                 .try_collect()?;
 
+        if data.iter().map(Vec::len).dedup().count() > 1 {
+            return Err("rows of different lengths");
+        }
+
         Ok(Grid::new(data))
     }
-}
\ No newline at end of file
+}
+
+// Small xorshift generator, so benchmarks need no extra dependencies
+#[derive(Debug, Clone)]
+pub struct XorShift(pub u64);
+
+impl XorShift {
+    pub fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}